@@ -0,0 +1,52 @@
+//! Measures the throughput and latency of a running server on `GET /api/posts`.
+//!
+//! Usage: cargo run --release --example load_test -- [<url>] [<concurrency>] [<requests>]
+
+use futures::future::join_all;
+use std::env;
+use std::time::{Duration, Instant};
+
+const DEFAULT_URL: &str = "http://127.0.0.1:8080/api/posts";
+const DEFAULT_CONCURRENCY: usize = 64;
+const DEFAULT_REQUESTS: usize = 10_000;
+
+/// Sends `requests` requests one after the other, returning the latency of each.
+async fn worker(url: String, requests: usize) -> Vec<Duration> {
+    let client = awc::Client::builder().timeout(Duration::from_secs(30)).finish();
+    let mut latencies = Vec::with_capacity(requests);
+
+    for _ in 0..requests {
+        let start = Instant::now();
+        let mut res = client.get(url.as_str()).send().await.expect("Request failed");
+        res.body().limit(usize::MAX).await.expect("Failed to read the body");
+        assert!(res.status().is_success(), "Unexpected status {}", res.status());
+        latencies.push(start.elapsed());
+    }
+
+    latencies
+}
+
+fn percentile(sorted: &[Duration], percent: usize) -> Duration {
+    sorted[(sorted.len() * percent / 100).min(sorted.len() - 1)]
+}
+
+#[actix_web::main]
+async fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let url = args.first().cloned().unwrap_or_else(|| String::from(DEFAULT_URL));
+    let concurrency = args.get(1).map_or(DEFAULT_CONCURRENCY, |n| n.parse().expect("Invalid concurrency"));
+    let requests = args.get(2).map_or(DEFAULT_REQUESTS, |n| n.parse().expect("Invalid request count"));
+    let per_worker = (requests / concurrency).max(1);
+
+    let start = Instant::now();
+    let results = join_all((0..concurrency).map(|_| worker(url.clone(), per_worker))).await;
+    let elapsed = start.elapsed();
+
+    let mut latencies: Vec<Duration> = results.into_iter().flatten().collect();
+    latencies.sort();
+
+    println!("{} requests to {} with {} connections in {:.2?}", latencies.len(), url, concurrency, elapsed);
+    println!("throughput: {:.0} req/s", latencies.len() as f64 / elapsed.as_secs_f64());
+    println!("p50: {:.2?}", percentile(&latencies, 50));
+    println!("p99: {:.2?}", percentile(&latencies, 99));
+}
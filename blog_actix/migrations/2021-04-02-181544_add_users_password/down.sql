@@ -0,0 +1,10 @@
+CREATE TABLE users_backup (
+    id INTEGER PRIMARY KEY NOT NULL,
+    username VARCHAR NOT NULL
+);
+
+INSERT INTO users_backup SELECT id, username FROM users;
+DROP TABLE users;
+ALTER TABLE users_backup RENAME TO users;
+
+CREATE UNIQUE INDEX username_unique_idx ON users(username);
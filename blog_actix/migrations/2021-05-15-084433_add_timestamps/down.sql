@@ -0,0 +1,65 @@
+CREATE TABLE posts_backup (
+    id INTEGER PRIMARY KEY NOT NULL,
+    user_id INTEGER NOT NULL REFERENCES users(id),
+    title VARCHAR NOT NULL,
+    body TEXT NOT NULL,
+    published BOOLEAN NOT NULL DEFAULT 0,
+    body_html TEXT NOT NULL DEFAULT '',
+    created_at TIMESTAMP NOT NULL DEFAULT '1970-01-01 00:00:00',
+    updated_at TIMESTAMP NOT NULL DEFAULT '1970-01-01 00:00:00'
+);
+
+INSERT INTO posts_backup SELECT id, user_id, title, body, published, body_html, created_at, updated_at FROM posts;
+DROP TABLE posts;
+ALTER TABLE posts_backup RENAME TO posts;
+
+CREATE TRIGGER posts_fts_insert AFTER INSERT ON posts BEGIN
+    INSERT INTO posts_fts(rowid, title, body) VALUES (new.id, new.title, new.body);
+END;
+
+CREATE TRIGGER posts_fts_delete AFTER DELETE ON posts BEGIN
+    INSERT INTO posts_fts(posts_fts, rowid, title, body) VALUES ('delete', old.id, old.title, old.body);
+END;
+
+CREATE TRIGGER posts_fts_update AFTER UPDATE OF title, body ON posts BEGIN
+    INSERT INTO posts_fts(posts_fts, rowid, title, body) VALUES ('delete', old.id, old.title, old.body);
+    INSERT INTO posts_fts(rowid, title, body) VALUES (new.id, new.title, new.body);
+END;
+
+CREATE TABLE comments_backup (
+    id INTEGER PRIMARY KEY NOT NULL,
+    user_id INTEGER NOT NULL REFERENCES users(id),
+    post_id INTEGER NOT NULL REFERENCES posts(id),
+    body TEXT NOT NULL,
+    parent_id INTEGER REFERENCES comments(id),
+    body_html TEXT NOT NULL DEFAULT ''
+);
+
+INSERT INTO comments_backup SELECT id, user_id, post_id, body, parent_id, body_html FROM comments;
+DROP TABLE comments;
+ALTER TABLE comments_backup RENAME TO comments;
+
+CREATE TRIGGER comments_fts_insert AFTER INSERT ON comments BEGIN
+    INSERT INTO comments_fts(rowid, body) VALUES (new.id, new.body);
+END;
+
+CREATE TRIGGER comments_fts_delete AFTER DELETE ON comments BEGIN
+    INSERT INTO comments_fts(comments_fts, rowid, body) VALUES ('delete', old.id, old.body);
+END;
+
+CREATE TRIGGER comments_fts_update AFTER UPDATE OF body ON comments BEGIN
+    INSERT INTO comments_fts(comments_fts, rowid, body) VALUES ('delete', old.id, old.body);
+    INSERT INTO comments_fts(rowid, body) VALUES (new.id, new.body);
+END;
+
+CREATE TABLE users_backup (
+    id INTEGER PRIMARY KEY NOT NULL,
+    username VARCHAR NOT NULL,
+    password_hash VARCHAR NOT NULL DEFAULT ''
+);
+
+INSERT INTO users_backup SELECT id, username, password_hash FROM users;
+DROP TABLE users;
+ALTER TABLE users_backup RENAME TO users;
+
+CREATE UNIQUE INDEX username_unique_idx ON users(username);
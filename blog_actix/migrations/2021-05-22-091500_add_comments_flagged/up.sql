@@ -0,0 +1 @@
+ALTER TABLE comments ADD COLUMN flagged BOOLEAN NOT NULL DEFAULT 0;
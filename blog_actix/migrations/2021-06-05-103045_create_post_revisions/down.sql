@@ -0,0 +1,33 @@
+CREATE TABLE posts_backup (
+    id INTEGER PRIMARY KEY NOT NULL,
+    user_id INTEGER NOT NULL REFERENCES users(id),
+    title VARCHAR NOT NULL,
+    body TEXT NOT NULL,
+    published BOOLEAN NOT NULL DEFAULT 0,
+    body_html TEXT NOT NULL DEFAULT '',
+    created_at TIMESTAMP NOT NULL DEFAULT '1970-01-01 00:00:00',
+    updated_at TIMESTAMP NOT NULL DEFAULT '1970-01-01 00:00:00',
+    published_at TIMESTAMP,
+    comments_closed BOOLEAN NOT NULL DEFAULT 0,
+    comments_moderated BOOLEAN NOT NULL DEFAULT 0
+);
+
+INSERT INTO posts_backup SELECT id, user_id, title, body, published, body_html, created_at, updated_at, published_at, comments_closed, comments_moderated FROM posts;
+DROP TABLE posts;
+ALTER TABLE posts_backup RENAME TO posts;
+
+CREATE TRIGGER posts_fts_insert AFTER INSERT ON posts BEGIN
+    INSERT INTO posts_fts(rowid, title, body) VALUES (new.id, new.title, new.body);
+END;
+
+CREATE TRIGGER posts_fts_delete AFTER DELETE ON posts BEGIN
+    INSERT INTO posts_fts(posts_fts, rowid, title, body) VALUES ('delete', old.id, old.title, old.body);
+END;
+
+CREATE TRIGGER posts_fts_update AFTER UPDATE OF title, body ON posts BEGIN
+    INSERT INTO posts_fts(posts_fts, rowid, title, body) VALUES ('delete', old.id, old.title, old.body);
+    INSERT INTO posts_fts(rowid, title, body) VALUES (new.id, new.title, new.body);
+END;
+
+DROP INDEX post_revisions_post_id_idx;
+DROP TABLE post_revisions;
@@ -0,0 +1,14 @@
+CREATE TABLE post_revisions (
+    id INTEGER PRIMARY KEY NOT NULL,
+    post_id INTEGER NOT NULL REFERENCES posts(id),
+    title VARCHAR NOT NULL,
+    body TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX post_revisions_post_id_idx ON post_revisions(post_id);
+
+INSERT INTO post_revisions (post_id, title, body, created_at)
+SELECT id, title, body, updated_at FROM posts;
+
+ALTER TABLE posts ADD COLUMN publish_at TIMESTAMP;
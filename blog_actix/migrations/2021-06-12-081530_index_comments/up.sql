@@ -0,0 +1,3 @@
+-- Comments are listed by post, and replies looked up by their parent.
+CREATE INDEX comments_parent_id_idx ON comments(parent_id);
+CREATE INDEX comments_post_id_idx ON comments(post_id);
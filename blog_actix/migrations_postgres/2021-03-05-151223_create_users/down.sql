@@ -0,0 +1,2 @@
+DROP INDEX username_unique_idx;
+DROP TABLE users;
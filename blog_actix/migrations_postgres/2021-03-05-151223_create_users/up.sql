@@ -0,0 +1,6 @@
+CREATE TABLE users (
+    id SERIAL PRIMARY KEY,
+    username VARCHAR NOT NULL
+);
+
+CREATE UNIQUE INDEX username_unique_idx ON users(username);
@@ -0,0 +1,3 @@
+-- The search query must use these exact expressions for the indexes to apply.
+CREATE INDEX posts_search_idx ON posts USING GIN (to_tsvector('english', title || ' ' || body));
+CREATE INDEX comments_search_idx ON comments USING GIN (to_tsvector('english', body));
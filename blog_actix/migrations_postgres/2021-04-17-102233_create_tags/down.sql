@@ -0,0 +1,3 @@
+DROP TABLE post_tags;
+DROP INDEX tag_name_unique_idx;
+DROP TABLE tags;
@@ -0,0 +1,2 @@
+ALTER TABLE posts ADD COLUMN body_html TEXT NOT NULL DEFAULT '';
+ALTER TABLE comments ADD COLUMN body_html TEXT NOT NULL DEFAULT '';
@@ -0,0 +1,7 @@
+ALTER TABLE posts DROP COLUMN published_at;
+
+ALTER TABLE comments DROP COLUMN updated_at;
+ALTER TABLE comments DROP COLUMN created_at;
+
+ALTER TABLE users DROP COLUMN updated_at;
+ALTER TABLE users DROP COLUMN created_at;
@@ -0,0 +1,6 @@
+ALTER TABLE posts DROP COLUMN comments_moderated;
+ALTER TABLE posts DROP COLUMN comments_closed;
+
+ALTER TABLE comments ADD COLUMN flagged BOOLEAN NOT NULL DEFAULT false;
+UPDATE comments SET flagged = true WHERE status <> 'approved';
+ALTER TABLE comments DROP COLUMN status;
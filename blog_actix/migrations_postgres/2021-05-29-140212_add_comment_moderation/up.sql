@@ -0,0 +1,6 @@
+ALTER TABLE comments ADD COLUMN status TEXT NOT NULL DEFAULT 'approved' CHECK (status IN ('pending', 'approved', 'rejected'));
+UPDATE comments SET status = 'pending' WHERE flagged;
+ALTER TABLE comments DROP COLUMN flagged;
+
+ALTER TABLE posts ADD COLUMN comments_closed BOOLEAN NOT NULL DEFAULT false;
+ALTER TABLE posts ADD COLUMN comments_moderated BOOLEAN NOT NULL DEFAULT false;
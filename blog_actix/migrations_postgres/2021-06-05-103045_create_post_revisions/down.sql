@@ -0,0 +1,4 @@
+ALTER TABLE posts DROP COLUMN publish_at;
+
+DROP INDEX post_revisions_post_id_idx;
+DROP TABLE post_revisions;
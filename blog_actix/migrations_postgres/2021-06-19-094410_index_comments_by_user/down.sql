@@ -0,0 +1 @@
+DROP INDEX comments_user_id_created_at_idx;
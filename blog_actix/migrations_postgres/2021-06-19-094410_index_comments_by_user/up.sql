@@ -0,0 +1,2 @@
+-- The duplicate check of new comments looks at the recent comments of their author.
+CREATE INDEX comments_user_id_created_at_idx ON comments(user_id, created_at);
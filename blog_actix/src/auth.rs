@@ -0,0 +1,104 @@
+use crate::db::DbConnection;
+use crate::errors::AppError;
+use crate::{models, Pool};
+use actix_web::dev::Payload;
+use actix_web::http::header;
+use actix_web::{web, FromRequest, HttpRequest};
+use jsonwebtoken::{DecodingKey, EncodingKey, Header, Validation};
+use std::future::Future;
+use std::pin::Pin;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const TOKEN_DURATION_SECS: u64 = 60 * 60 * 24;
+const BEARER_PREFIX: &str = "Bearer ";
+
+/// Key used to sign and verify session tokens.
+#[derive(Clone)]
+pub struct Secret(pub String);
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Claims {
+    sub: i32,
+    exp: u64,
+}
+
+pub fn hash_password(password: &str) -> Result<String, AppError> {
+    bcrypt::hash(password, bcrypt::DEFAULT_COST).map_err(Into::into)
+}
+
+pub fn verify_password(password: &str, hash: &str) -> bool {
+    bcrypt::verify(password, hash).unwrap_or(false)
+}
+
+pub fn issue_token(secret: &Secret, user: &models::User) -> Result<String, AppError> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("System clock is before the unix epoch")
+        .as_secs();
+    let claims = Claims {
+        sub: user.id,
+        exp: now + TOKEN_DURATION_SECS,
+    };
+
+    jsonwebtoken::encode(&Header::default(), &claims, &EncodingKey::from_secret(secret.0.as_bytes()))
+        .map_err(Into::into)
+}
+
+fn token_claims(req: &HttpRequest) -> Result<Claims, AppError> {
+    let secret = req.app_data::<web::Data<Secret>>().ok_or(AppError::Unauthorized)?;
+    let token = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| value.starts_with(BEARER_PREFIX))
+        .map(|value| &value[BEARER_PREFIX.len()..])
+        .ok_or(AppError::Unauthorized)?;
+
+    jsonwebtoken::decode::<Claims>(token, &DecodingKey::from_secret(secret.0.as_bytes()), &Validation::default())
+        .map(|data| data.claims)
+        .map_err(Into::into)
+}
+
+/// Id of the user of a valid bearer token, without loading the user.
+pub fn token_user_id(req: &HttpRequest) -> Option<i32> {
+    token_claims(req).ok().map(|claims| claims.sub)
+}
+
+/// Extractor resolving the user identified by the request bearer token.
+pub struct AuthUser(pub models::User);
+
+impl AuthUser {
+    /// Loads a post, failing with `Forbidden` unless this user wrote it.
+    pub async fn authored_post(&self, connection: &mut DbConnection, post_id: i32) -> Result<models::Post, AppError> {
+        let post = models::find_post(connection, post_id).await?;
+        if post.user_id != self.0.id {
+            return Err(AppError::Forbidden);
+        }
+
+        Ok(post)
+    }
+}
+
+impl FromRequest for AuthUser {
+    type Error = AppError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let claims = token_claims(req);
+        let pool = req.app_data::<web::Data<Pool>>().cloned();
+
+        Box::pin(async move {
+            let claims = claims?;
+            let pool = pool.ok_or(AppError::Unauthorized)?;
+
+            let mut conn = pool.get().await?;
+            models::find_user(&mut conn, models::UserKey::Id(claims.sub))
+                .await
+                .map(AuthUser)
+                .map_err(|e| match e {
+                    AppError::RecordNotFound => AppError::Unauthorized,
+                    e => e,
+                })
+        })
+    }
+}
@@ -0,0 +1,155 @@
+use blog_actix::models::{self, PostChanges, UserKey};
+use blog_actix::{establish, hash_password, validate_password, validate_username, AppError, Blog, BlogConfig, DbConnection};
+use dotenv::dotenv;
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, IsTerminal, Read, Write};
+use std::process;
+
+const USAGE: &str = "Usage: blog-admin [--config <path>] <command>
+
+Commands:
+    users list
+    users create <username>   the password is read from stdin
+    users rename <id> <username>
+    users delete <id>
+    posts publish <id>
+    posts unpublish <id>
+    posts delete <id>
+    comments purge <user-id>
+    export [<file>]         write every row as JSON, to stdout by default
+    import [<file>]         load a JSON export into an empty database, from stdin by default";
+
+#[actix_web::main]
+async fn main() {
+    dotenv().ok();
+
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let mut config_path = None;
+    if args.first().map(String::as_str) == Some("--config") {
+        if args.len() < 2 {
+            exit_with(USAGE);
+        }
+        config_path = Some(args.remove(1));
+        args.remove(0);
+    }
+
+    let config = BlogConfig::load_without_secret(config_path.as_deref()).unwrap_or_else(|err| exit_with(&err.to_string()));
+    let mut connection = establish(&config.database_url)
+        .await
+        .unwrap_or_else(|err| exit_with(&err.to_string()));
+    Blog::new(config).check_migrations().unwrap_or_else(|err| exit_with(&err.to_string()));
+
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    if let Err(err) = run(&mut connection, &args).await {
+        exit_with(&err.to_string());
+    }
+}
+
+async fn run(connection: &mut DbConnection, args: &[&str]) -> Result<(), AppError> {
+    match args {
+        ["users", "list"] => {
+            for user in models::all_users(connection).await? {
+                println!("{}\t{}\t{}", user.id, user.username, user.created_at);
+            }
+        }
+        ["users", "create", username] => {
+            check_username(username);
+            let password = read_password();
+            let user = models::create_user(connection, username, &hash_password(&password)?).await?;
+            println!("Created user {} ({})", user.id, user.username);
+        }
+        ["users", "rename", id, username] => {
+            check_username(username);
+            let user = models::rename_user(connection, parse_id(id), username).await?;
+            println!("Renamed user {} to {}", user.id, user.username);
+        }
+        ["users", "delete", id] => {
+            let user = models::delete_user(connection, parse_id(id)).await?;
+            println!("Deleted user {} ({}) with their posts and comments", user.id, user.username);
+        }
+        ["posts", "publish", id] => {
+            let post = models::publish_post(connection, parse_id(id)).await?;
+            println!("Published post {}", post.id);
+        }
+        ["posts", "unpublish", id] => {
+            let changes = PostChanges {
+                published: Some(false),
+                ..Default::default()
+            };
+            let post = models::update_post(connection, parse_id(id), &changes).await?;
+            println!("Unpublished post {}", post.id);
+        }
+        ["posts", "delete", id] => {
+            let post = models::delete_post(connection, parse_id(id)).await?;
+            println!("Deleted post {} ({})", post.id, post.title);
+        }
+        ["comments", "purge", user_id] => {
+            let user = models::find_user(connection, UserKey::Id(parse_id(user_id))).await?;
+            let count = models::delete_user_comments(connection, user.id).await?;
+            println!("Deleted {} comment(s) of {}", count, user.username);
+        }
+        ["export"] | ["export", _] => {
+            let dump = models::export(connection).await?;
+            let json = serde_json::to_string_pretty(&dump).expect("Failed to serialize the export");
+            match args.get(1) {
+                Some(path) => fs::write(path, json).unwrap_or_else(|err| exit_with(&err.to_string())),
+                None => println!("{}", json),
+            }
+        }
+        ["import"] | ["import", _] => {
+            let json = match args.get(1) {
+                Some(path) => fs::read_to_string(path),
+                None => {
+                    let mut json = String::new();
+                    io::stdin().read_to_string(&mut json).map(|_| json)
+                }
+            }
+            .unwrap_or_else(|err| exit_with(&err.to_string()));
+            let dump: models::Dump = serde_json::from_str(&json).unwrap_or_else(|err| exit_with(&err.to_string()));
+            models::import(connection, &dump).await?;
+            println!(
+                "Imported {} user(s), {} post(s) and {} comment(s)",
+                dump.users.len(),
+                dump.posts.len(),
+                dump.comments.len()
+            );
+        }
+        _ => exit_with(USAGE),
+    }
+
+    Ok(())
+}
+
+fn parse_id(id: &str) -> i32 {
+    id.parse().unwrap_or_else(|_| exit_with(&format!("Invalid id: {}", id)))
+}
+
+/// Holds usernames to the same rules as the API.
+fn check_username(username: &str) {
+    if let Err(err) = validate_username(username) {
+        exit_with(&format!("Invalid username: {}", err.message.unwrap_or_default()));
+    }
+}
+
+/// Reads the password from the first line of stdin, which keeps it out of the
+/// process list and shell history, and holds it to the same rules as the API.
+fn read_password() -> String {
+    if io::stdin().is_terminal() {
+        eprint!("Password: ");
+        let _ = io::stderr().flush();
+    }
+    let mut password = String::new();
+    io::stdin().lock().read_line(&mut password).unwrap_or_else(|err| exit_with(&err.to_string()));
+    let password = password.trim_end_matches(['\r', '\n']).to_string();
+
+    if let Err(err) = validate_password(&password) {
+        exit_with(&format!("Invalid password: {}", err.message.unwrap_or_default()));
+    }
+    password
+}
+
+fn exit_with(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(2)
+}
@@ -0,0 +1,119 @@
+//! In-process cache of the post listings served by the API.
+//!
+//! The routes clear it on every write that can change a listing. Entries also
+//! expire after `MAX_AGE`, which bounds how long the writes of `blog-admin` or
+//! of another server process on the same database take to show up.
+
+use crate::models::{PageRequest, PostSort};
+use actix_web::web::Bytes;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+const MAX_AGE: Duration = Duration::from_secs(60);
+/// Distinct listings kept at once; the cache starts over when it is full.
+const MAX_ENTRIES: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ListingKey {
+    user_id: Option<i32>,
+    limit: i64,
+    after: Option<i32>,
+    sort: PostSort,
+    tag: Option<String>,
+    /// Authors also see the comments waiting for approval on their posts.
+    viewer: Option<i32>,
+}
+
+impl ListingKey {
+    pub fn new(user_id: Option<i32>, page: &PageRequest, tag: Option<&str>, viewer: Option<i32>) -> Self {
+        ListingKey {
+            user_id,
+            limit: page.limit,
+            after: page.after,
+            sort: page.sort,
+            tag: tag.map(String::from),
+            viewer,
+        }
+    }
+}
+
+/// Entity tag of a response body, the same across builds and processes.
+pub fn entity_tag(body: &[u8]) -> String {
+    let digest = Sha256::digest(body);
+    let hex: String = digest[..16].iter().map(|byte| format!("{:02x}", byte)).collect();
+
+    format!("\"{}\"", hex)
+}
+
+/// A listing serialized as JSON, with the entity tag of that body.
+pub struct Listing {
+    pub etag: String,
+    pub body: Bytes,
+    cached_at: Instant,
+}
+
+impl Listing {
+    pub fn new(body: Vec<u8>) -> Self {
+        Listing {
+            etag: entity_tag(&body),
+            body: Bytes::from(body),
+            cached_at: Instant::now(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Entries {
+    /// Bumped by every invalidation.
+    generation: u64,
+    listings: HashMap<ListingKey, Arc<Listing>>,
+}
+
+/// Shared by every worker of the server, cloning it shares the same entries.
+#[derive(Clone, Default)]
+pub struct ListingCache {
+    entries: Arc<Mutex<Entries>>,
+}
+
+impl ListingCache {
+    fn lock(&self) -> MutexGuard<'_, Entries> {
+        // The entries are consistent after any panic, nothing to recover.
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn get(&self, key: &ListingKey) -> Option<Arc<Listing>> {
+        self.lock()
+            .listings
+            .get(key)
+            .filter(|listing| listing.cached_at.elapsed() < MAX_AGE)
+            .cloned()
+    }
+
+    /// To read before loading a listing, and give back to `insert`.
+    pub fn generation(&self) -> u64 {
+        self.lock().generation
+    }
+
+    /// Keeps `listing` unless the cache was invalidated since `generation`:
+    /// it may then have been loaded before that write.
+    pub fn insert(&self, key: ListingKey, generation: u64, listing: Listing) -> Arc<Listing> {
+        let listing = Arc::new(listing);
+        let mut entries = self.lock();
+        if entries.generation == generation {
+            if entries.listings.len() >= MAX_ENTRIES {
+                entries.listings.clear();
+            }
+            entries.listings.insert(key, listing.clone());
+        }
+
+        listing
+    }
+
+    pub fn invalidate(&self) {
+        let mut entries = self.lock();
+        entries.generation = entries.generation.wrapping_add(1);
+        entries.listings.clear();
+    }
+}
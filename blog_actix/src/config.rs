@@ -0,0 +1,284 @@
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Read when no `--config` path is given and the file exists.
+pub const DEFAULT_CONFIG_PATH: &str = "blog.toml";
+
+/// actix-web's default access log format.
+const DEFAULT_LOG_FORMAT: &str = r#"%a "%r" %s %b "%{Referer}i" "%{User-Agent}i" %T"#;
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Read(String, std::io::Error),
+    Parse(String, toml::de::Error),
+    Env(&'static str, String),
+    Invalid(&'static str, &'static str),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Read(path, err) => write!(f, "Cannot read {}: {}", path, err),
+            ConfigError::Parse(path, err) => write!(f, "Cannot parse {}: {}", path, err),
+            ConfigError::Env(name, err) => write!(f, "Invalid value for {}: {}", name, err),
+            ConfigError::Invalid(field, reason) => write!(f, "Invalid {}: {}", field, reason),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Sizing of the database connection pool.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PoolConfig {
+    pub max_size: u32,
+    /// Seconds a request waits for a free connection before getting a `503`.
+    pub connection_timeout: u64,
+}
+
+impl PoolConfig {
+    pub fn connection_timeout(&self) -> Duration {
+        Duration::from_secs(self.connection_timeout)
+    }
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        PoolConfig {
+            max_size: 10,
+            connection_timeout: 5,
+        }
+    }
+}
+
+/// Token bucket: up to `burst` requests at once, refilled by `per_minute`.
+/// A `burst` of 0 turns the limit off.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimitConfig {
+    pub burst: u32,
+    pub per_minute: u32,
+}
+
+/// Request body size, in bytes, and request rates per client.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    pub json_body: usize,
+    /// `POST /api/users`, per IP address.
+    pub create_user: RateLimitConfig,
+    /// `POST /api/posts/{id}/comments`, per IP address and per user.
+    pub create_comment: RateLimitConfig,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        LimitsConfig {
+            json_body: 256 * 1024,
+            create_user: RateLimitConfig { burst: 5, per_minute: 1 },
+            create_comment: RateLimitConfig { burst: 10, per_minute: 5 },
+        }
+    }
+}
+
+/// Server settings, read from a TOML file and then overridden by environment variables.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BlogConfig {
+    pub bind_address: String,
+    pub port: u16,
+    /// Public URL of the site, which the feeds link to.
+    pub site_url: String,
+    /// Number of worker threads, one per CPU core when unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub workers: Option<usize>,
+    pub database_url: String,
+    /// Apply pending migrations at startup. When off, the server refuses to start until `blog_actix migrate` has run.
+    pub migrate_on_start: bool,
+    pub secret_key: String,
+    /// Access log format, see `actix_web::middleware::Logger`.
+    pub log_format: String,
+    /// Origins allowed to call the API from a browser. CORS is disabled when empty.
+    pub cors_origins: Vec<String>,
+    /// Seconds between two runs of the scheduler publishing the posts whose `publish_at` has come.
+    pub publish_interval: u64,
+    pub pool: PoolConfig,
+    pub limits: LimitsConfig,
+}
+
+impl Default for BlogConfig {
+    fn default() -> Self {
+        BlogConfig {
+            bind_address: String::from("127.0.0.1"),
+            port: 8080,
+            site_url: String::from("http://localhost:8080"),
+            workers: None,
+            database_url: String::new(),
+            migrate_on_start: true,
+            secret_key: String::new(),
+            log_format: String::from(DEFAULT_LOG_FORMAT),
+            cors_origins: Vec::new(),
+            publish_interval: 30,
+            pool: PoolConfig::default(),
+            limits: LimitsConfig::default(),
+        }
+    }
+}
+
+fn env_var<T>(name: &'static str) -> Result<Option<T>, ConfigError>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    match env::var(name) {
+        Ok(value) => value
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|err: T::Err| ConfigError::Env(name, err.to_string())),
+        Err(_) => Ok(None),
+    }
+}
+
+impl BlogConfig {
+    pub fn publish_interval(&self) -> Duration {
+        Duration::from_secs(self.publish_interval)
+    }
+
+    /// Loads `path`, or `blog.toml` if present, applies the environment overrides and validates the result.
+    pub fn load(path: Option<&str>) -> Result<Self, ConfigError> {
+        let config = Self::load_without_secret(path)?;
+        if config.secret_key.trim().is_empty() {
+            return Err(ConfigError::Invalid("secret_key", "must be set (or SECRET_KEY)"));
+        }
+
+        Ok(config)
+    }
+
+    /// Like `load`, but `secret_key` may be left unset, for tools that never sign tokens.
+    pub fn load_without_secret(path: Option<&str>) -> Result<Self, ConfigError> {
+        let mut config = match path {
+            Some(path) => Self::from_file(path)?,
+            None if Path::new(DEFAULT_CONFIG_PATH).exists() => Self::from_file(DEFAULT_CONFIG_PATH)?,
+            None => BlogConfig::default(),
+        };
+
+        config.apply_env()?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn from_file(path: &str) -> Result<Self, ConfigError> {
+        let contents = fs::read_to_string(path).map_err(|err| ConfigError::Read(path.to_string(), err))?;
+        toml::from_str(&contents).map_err(|err| ConfigError::Parse(path.to_string(), err))
+    }
+
+    fn apply_env(&mut self) -> Result<(), ConfigError> {
+        if let Some(bind_address) = env_var("BLOG_BIND_ADDRESS")? {
+            self.bind_address = bind_address;
+        }
+        if let Some(port) = env_var("BLOG_PORT")? {
+            self.port = port;
+        }
+        if let Some(site_url) = env_var::<String>("BLOG_SITE_URL")? {
+            self.site_url = site_url;
+        }
+        if let Some(workers) = env_var("BLOG_WORKERS")? {
+            self.workers = Some(workers);
+        }
+        if let Some(database_url) = env_var("DATABASE_URL")? {
+            self.database_url = database_url;
+        }
+        if let Some(migrate_on_start) = env_var("BLOG_MIGRATE_ON_START")? {
+            self.migrate_on_start = migrate_on_start;
+        }
+        if let Some(secret_key) = env_var("SECRET_KEY")? {
+            self.secret_key = secret_key;
+        }
+        if let Some(log_format) = env_var("BLOG_LOG_FORMAT")? {
+            self.log_format = log_format;
+        }
+        if let Some(origins) = env_var::<String>("BLOG_CORS_ORIGINS")? {
+            self.cors_origins = origins
+                .split(',')
+                .map(str::trim)
+                .filter(|origin| !origin.is_empty())
+                .map(String::from)
+                .collect();
+        }
+        if let Some(publish_interval) = env_var("BLOG_PUBLISH_INTERVAL")? {
+            self.publish_interval = publish_interval;
+        }
+        if let Some(max_size) = env_var("BLOG_POOL_MAX_SIZE")? {
+            self.pool.max_size = max_size;
+        }
+        if let Some(connection_timeout) = env_var("BLOG_POOL_CONNECTION_TIMEOUT")? {
+            self.pool.connection_timeout = connection_timeout;
+        }
+        if let Some(json_body) = env_var("BLOG_JSON_BODY_LIMIT")? {
+            self.limits.json_body = json_body;
+        }
+
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.bind_address.trim().is_empty() {
+            return Err(ConfigError::Invalid("bind_address", "must not be blank"));
+        }
+        if self.port == 0 {
+            return Err(ConfigError::Invalid("port", "must be between 1 and 65535"));
+        }
+        if !(self.site_url.starts_with("http://") || self.site_url.starts_with("https://")) {
+            return Err(ConfigError::Invalid("site_url", "must be an http(s) URL"));
+        }
+        if self.workers == Some(0) {
+            return Err(ConfigError::Invalid("workers", "must be at least 1"));
+        }
+        if self.database_url.trim().is_empty() {
+            return Err(ConfigError::Invalid("database_url", "must be set (or DATABASE_URL)"));
+        }
+        if self.log_format.trim().is_empty() {
+            return Err(ConfigError::Invalid("log_format", "must not be blank"));
+        }
+        let valid_origin = |origin: &String| origin.starts_with("http://") || origin.starts_with("https://");
+        if !self.cors_origins.iter().all(valid_origin) {
+            return Err(ConfigError::Invalid("cors_origins", "must be http(s) origins"));
+        }
+        if self.publish_interval == 0 {
+            return Err(ConfigError::Invalid("publish_interval", "must be at least 1 second"));
+        }
+        if self.pool.max_size == 0 {
+            return Err(ConfigError::Invalid("pool.max_size", "must be at least 1"));
+        }
+        if self.pool.connection_timeout == 0 {
+            return Err(ConfigError::Invalid("pool.connection_timeout", "must be at least 1 second"));
+        }
+        if self.limits.json_body == 0 {
+            return Err(ConfigError::Invalid("limits.json_body", "must be at least 1 byte"));
+        }
+        if self.limits.create_user.burst > 0 && self.limits.create_user.per_minute == 0 {
+            return Err(ConfigError::Invalid("limits.create_user.per_minute", "must be at least 1"));
+        }
+        if self.limits.create_comment.burst > 0 && self.limits.create_comment.per_minute == 0 {
+            return Err(ConfigError::Invalid("limits.create_comment.per_minute", "must be at least 1"));
+        }
+
+        Ok(())
+    }
+
+    /// The effective configuration as TOML, with the secret key redacted.
+    pub fn to_toml(&self) -> String {
+        let redacted = BlogConfig {
+            secret_key: String::from("<redacted>"),
+            ..self.clone()
+        };
+
+        toml::to_string_pretty(&redacted).expect("Failed to serialize the configuration")
+    }
+}
@@ -0,0 +1,77 @@
+//! The database backend, chosen at build time with the `sqlite` (default) or `postgres` feature.
+
+#[cfg(feature = "sqlite")]
+use diesel::ConnectionError;
+use diesel::ConnectionResult;
+#[cfg(feature = "sqlite")]
+use diesel_async::pooled_connection::ManagerConfig;
+use diesel_async::pooled_connection::deadpool::{Pool, PoolBuilder};
+use diesel_async::pooled_connection::AsyncDieselConnectionManager;
+#[cfg(feature = "sqlite")]
+use diesel_async::SimpleAsyncConnection;
+use diesel_async::AsyncConnection;
+use futures::future::{BoxFuture, FutureExt};
+
+#[cfg(all(feature = "sqlite", feature = "postgres"))]
+compile_error!("the `sqlite` and `postgres` features can't be enabled together");
+
+#[cfg(not(any(feature = "sqlite", feature = "postgres")))]
+compile_error!("one of the `sqlite` or `postgres` features must be enabled");
+
+/// Blocking connection, for the migrations which diesel only runs synchronously.
+#[cfg(feature = "sqlite")]
+pub type SyncConnection = diesel::sqlite::SqliteConnection;
+/// SQLite has no asynchronous driver: each query runs on a blocking thread of
+/// the runtime, keeping the server's own threads free meanwhile.
+#[cfg(feature = "sqlite")]
+pub type DbConnection = diesel_async::sync_connection_wrapper::SyncConnectionWrapper<SyncConnection>;
+#[cfg(feature = "sqlite")]
+pub type DbBackend = diesel::sqlite::Sqlite;
+
+#[cfg(feature = "postgres")]
+pub type SyncConnection = diesel::pg::PgConnection;
+#[cfg(feature = "postgres")]
+pub type DbConnection = diesel_async::AsyncPgConnection;
+#[cfg(feature = "postgres")]
+pub type DbBackend = diesel::pg::Pg;
+
+/// Milliseconds a SQLite connection waits for another connection's write lock
+/// before failing with "database is locked".
+#[cfg(feature = "sqlite")]
+const BUSY_TIMEOUT_MS: u32 = 5000;
+
+/// Opens a connection with the settings the pool gives its own, for tools such as `blog-admin`.
+#[cfg(feature = "sqlite")]
+pub fn establish(database_url: &str) -> BoxFuture<'_, ConnectionResult<DbConnection>> {
+    let database_url = database_url.to_string();
+
+    async move {
+        let mut connection = DbConnection::establish(&database_url).await?;
+        connection
+            .batch_execute(&format!("PRAGMA busy_timeout = {}", BUSY_TIMEOUT_MS))
+            .await
+            .map_err(ConnectionError::CouldntSetupConfiguration)?;
+
+        Ok(connection)
+    }
+    .boxed()
+}
+
+#[cfg(feature = "postgres")]
+pub fn establish(database_url: &str) -> BoxFuture<'_, ConnectionResult<DbConnection>> {
+    DbConnection::establish(database_url).boxed()
+}
+
+/// Pool builder with the per connection settings of the backend.
+#[cfg(feature = "sqlite")]
+pub fn pool_builder(database_url: &str) -> PoolBuilder<DbConnection> {
+    let mut config = ManagerConfig::default();
+    config.custom_setup = Box::new(establish);
+
+    Pool::builder(AsyncDieselConnectionManager::new_with_config(database_url, config))
+}
+
+#[cfg(feature = "postgres")]
+pub fn pool_builder(database_url: &str) -> PoolBuilder<DbConnection> {
+    Pool::builder(AsyncDieselConnectionManager::new(database_url))
+}
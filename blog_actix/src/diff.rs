@@ -0,0 +1,19 @@
+//! Line diffs between the revisions of a post.
+
+use similar::TextDiff;
+
+/// Unchanged lines shown around each change.
+const CONTEXT_LINES: usize = 3;
+
+/// A unified diff from `old` to `new`, empty when they are the same.
+pub fn unified(old: &str, new: &str, old_name: &str, new_name: &str) -> String {
+    if old == new {
+        return String::new();
+    }
+
+    let diff = TextDiff::from_lines(old, new);
+    diff.unified_diff()
+        .context_radius(CONTEXT_LINES)
+        .header(old_name, new_name)
+        .to_string()
+}
@@ -1,8 +1,12 @@
-use actix_web::error::BlockingError;
-use actix_web::web::HttpResponse;
+use actix_web::error::{BlockingError, JsonPayloadError};
+use actix_web::http::header;
+use actix_web::HttpResponse;
 use diesel::result::DatabaseErrorKind::UniqueViolation;
 use diesel::result::Error::{DatabaseError, NotFound};
+use diesel_async::pooled_connection::deadpool::PoolError;
+use std::collections::HashMap;
 use std::fmt;
+use validator::ValidationErrors;
 
 #[derive(Debug)]
 pub enum AppError {
@@ -10,6 +14,22 @@ pub enum AppError {
     RecordNotFound,
     DatabaseError(diesel::result::Error),
     OperationCancelled,
+    PoolUnavailable(PoolError),
+    Unauthorized,
+    Forbidden,
+    InvalidPage,
+    InvalidSearch,
+    InvalidTag,
+    InvalidParent,
+    CommentsClosed,
+    InvalidSchedule,
+    InvalidPayload(String),
+    PayloadTooLarge,
+    /// Seconds until the client may try again.
+    RateLimited(u64),
+    Validation(ValidationErrors),
+    HashError(bcrypt::BcryptError),
+    TemplateError(askama::Error),
 }
 
 impl fmt::Display for AppError {
@@ -19,6 +39,21 @@ impl fmt::Display for AppError {
             AppError::RecordNotFound => write!(f, "This record does not exist"),
             AppError::DatabaseError(err) => write!(f, "Database error: {:?}", err),
             AppError::OperationCancelled => write!(f, "The operation was cancelled"),
+            AppError::PoolUnavailable(err) => write!(f, "No database connection available: {}", err),
+            AppError::Unauthorized => write!(f, "Missing or invalid credentials"),
+            AppError::Forbidden => write!(f, "Not allowed to access this record"),
+            AppError::InvalidPage => write!(f, "Invalid page limit or cursor"),
+            AppError::InvalidSearch => write!(f, "The search query is empty"),
+            AppError::InvalidTag => write!(f, "The tag name is empty"),
+            AppError::InvalidParent => write!(f, "The parent comment does not belong to this post"),
+            AppError::CommentsClosed => write!(f, "Comments are closed on this post"),
+            AppError::InvalidSchedule => write!(f, "Only unpublished posts can be scheduled, at a later time"),
+            AppError::InvalidPayload(err) => write!(f, "Invalid request body: {}", err),
+            AppError::PayloadTooLarge => write!(f, "The request body is too large"),
+            AppError::RateLimited(_) => write!(f, "Too many requests, slow down"),
+            AppError::Validation(_) => write!(f, "The request has invalid fields"),
+            AppError::HashError(err) => write!(f, "Password hash error: {:?}", err),
+            AppError::TemplateError(err) => write!(f, "Template error: {:?}", err),
         }
     }
 }
@@ -33,32 +68,113 @@ impl From<diesel::result::Error> for AppError {
     }
 }
 
-impl From<BlockingError<AppError>> for AppError {
-    fn from(err: BlockingError<AppError>) -> Self {
+impl From<PoolError> for AppError {
+    fn from(err: PoolError) -> Self {
+        AppError::PoolUnavailable(err)
+    }
+}
+
+impl From<bcrypt::BcryptError> for AppError {
+    fn from(err: bcrypt::BcryptError) -> Self {
+        AppError::HashError(err)
+    }
+}
+
+impl From<askama::Error> for AppError {
+    fn from(err: askama::Error) -> Self {
+        AppError::TemplateError(err)
+    }
+}
+
+impl From<jsonwebtoken::errors::Error> for AppError {
+    fn from(_: jsonwebtoken::errors::Error) -> Self {
+        AppError::Unauthorized
+    }
+}
+
+impl From<ValidationErrors> for AppError {
+    fn from(errors: ValidationErrors) -> Self {
+        AppError::Validation(errors)
+    }
+}
+
+impl From<JsonPayloadError> for AppError {
+    fn from(err: JsonPayloadError) -> Self {
         match err {
-            BlockingError::Error(inner) => inner,
-            BlockingError::Canceled => AppError::OperationCancelled,
+            JsonPayloadError::Overflow { .. } | JsonPayloadError::OverflowKnownLength { .. } => AppError::PayloadTooLarge,
+            _ => AppError::InvalidPayload(format!("{}", err)),
         }
     }
 }
 
+/// `web::block` only fails on its own when the blocking thread pool is gone or
+/// the closure panicked; errors of the closure come back in its `Ok` value.
+impl From<BlockingError> for AppError {
+    fn from(_: BlockingError) -> Self {
+        AppError::OperationCancelled
+    }
+}
+
+/// Seconds clients are asked to wait when every database connection is busy.
+const RETRY_AFTER_SECS: u32 = 5;
+
 #[derive(Debug, Serialize)]
 struct ErrorResponse {
     err: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fields: Option<HashMap<&'static str, Vec<String>>>,
+}
+
+/// Messages of every failed rule, keyed by field name.
+fn field_errors(errors: &ValidationErrors) -> HashMap<&'static str, Vec<String>> {
+    errors
+        .field_errors()
+        .into_iter()
+        .map(|(field, errors)| {
+            let messages = errors
+                .iter()
+                .map(|error| match &error.message {
+                    Some(message) => message.to_string(),
+                    None => error.code.to_string(),
+                })
+                .collect();
+            (field, messages)
+        })
+        .collect()
 }
 
 impl actix_web::ResponseError for AppError {
     fn error_response(&self) -> HttpResponse {
         let err = format!("{}", self);
         let mut builder = match self {
-            AppError::RecordAlreadyExists => HttpResponse::BadRequest(),
+            AppError::RecordAlreadyExists
+            | AppError::InvalidPage
+            | AppError::InvalidSearch
+            | AppError::InvalidTag
+            | AppError::InvalidParent
+            | AppError::InvalidSchedule
+            | AppError::InvalidPayload(_) => HttpResponse::BadRequest(),
+            AppError::PayloadTooLarge => HttpResponse::PayloadTooLarge(),
+            AppError::Validation(_) => HttpResponse::UnprocessableEntity(),
             AppError::RecordNotFound => HttpResponse::NotFound(),
+            AppError::Unauthorized => HttpResponse::Unauthorized(),
+            AppError::Forbidden | AppError::CommentsClosed => HttpResponse::Forbidden(),
+            AppError::RateLimited(retry_after) => {
+                let mut builder = HttpResponse::TooManyRequests();
+                builder.insert_header((header::RETRY_AFTER, retry_after.to_string()));
+                builder
+            }
+            AppError::PoolUnavailable(_) => {
+                let mut builder = HttpResponse::ServiceUnavailable();
+                builder.insert_header((header::RETRY_AFTER, RETRY_AFTER_SECS.to_string()));
+                builder
+            }
             _ => HttpResponse::InternalServerError(),
         };
-        builder.json(ErrorResponse { err })
-    }
-
-    fn render_response(&self) -> HttpResponse {
-        self.error_response()
+        let fields = match self {
+            AppError::Validation(errors) => Some(field_errors(errors)),
+            _ => None,
+        };
+        builder.json(ErrorResponse { err, fields })
     }
 }
@@ -0,0 +1,119 @@
+use crate::models::{Post, User};
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use std::fmt::Write;
+
+/// Public URL of the site, from the configuration rather than the request
+/// `Host` header, which any client can set.
+#[derive(Clone)]
+pub struct SiteUrl(pub String);
+
+pub struct Feed {
+    pub title: String,
+    pub site_url: String,
+    pub feed_url: String,
+    pub entries: Vec<(Post, User)>,
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+fn utc(time: NaiveDateTime) -> DateTime<Utc> {
+    Utc.from_utc_datetime(&time)
+}
+
+impl Feed {
+    /// Most recent change of any entry, used for `Last-Modified` and the feed dates.
+    pub fn updated(&self) -> Option<NaiveDateTime> {
+        self.entries.iter().map(|(post, _)| post.updated_at).max()
+    }
+
+    fn post_url(&self, post: &Post) -> String {
+        format!("{}/posts/{}", self.site_url, post.id)
+    }
+
+    pub fn to_rss(&self) -> String {
+        let mut xml = String::new();
+        xml.push_str(r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+        xml.push_str(r#"<rss version="2.0" xmlns:dc="http://purl.org/dc/elements/1.1/"><channel>"#);
+        let _ = write!(
+            xml,
+            "<title>{}</title><link>{}/</link><description>{}</description>",
+            escape(&self.title),
+            escape(&self.site_url),
+            escape(&self.title),
+        );
+        if let Some(updated) = self.updated() {
+            let _ = write!(xml, "<lastBuildDate>{}</lastBuildDate>", utc(updated).to_rfc2822());
+        }
+
+        for (post, user) in &self.entries {
+            let url = escape(&self.post_url(post));
+            let _ = write!(
+                xml,
+                "<item><title>{}</title><link>{}</link><guid isPermaLink=\"true\">{}</guid>\
+                 <pubDate>{}</pubDate><dc:creator>{}</dc:creator><description>{}</description></item>",
+                escape(&post.title),
+                url,
+                url,
+                utc(post.published_at.unwrap_or(post.created_at)).to_rfc2822(),
+                escape(&user.username),
+                escape(&post.body_html),
+            );
+        }
+
+        xml.push_str("</channel></rss>");
+        xml
+    }
+
+    pub fn to_atom(&self) -> String {
+        // An empty feed has a fixed date, so its body and ETag don't change between requests.
+        let updated = self.updated().map(utc).unwrap_or(DateTime::UNIX_EPOCH);
+
+        let mut xml = String::new();
+        xml.push_str(r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+        xml.push_str(r#"<feed xmlns="http://www.w3.org/2005/Atom">"#);
+        let _ = write!(
+            xml,
+            "<title>{}</title><id>{}</id><updated>{}</updated>\
+             <link rel=\"self\" href=\"{}\"/><link href=\"{}/\"/>",
+            escape(&self.title),
+            escape(&self.feed_url),
+            updated.to_rfc3339(),
+            escape(&self.feed_url),
+            escape(&self.site_url),
+        );
+
+        for (post, user) in &self.entries {
+            let url = escape(&self.post_url(post));
+            let _ = write!(
+                xml,
+                "<entry><title>{}</title><id>{}</id><link href=\"{}\"/>\
+                 <published>{}</published><updated>{}</updated><author><name>{}</name></author>\
+                 <content type=\"html\">{}</content></entry>",
+                escape(&post.title),
+                url,
+                url,
+                utc(post.published_at.unwrap_or(post.created_at)).to_rfc3339(),
+                utc(post.updated_at).to_rfc3339(),
+                escape(&user.username),
+                escape(&post.body_html),
+            );
+        }
+
+        xml.push_str("</feed>");
+        xml
+    }
+}
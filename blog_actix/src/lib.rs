@@ -3,43 +3,225 @@ extern crate diesel;
 #[macro_use]
 extern crate serde_derive;
 
-use actix_web::{middleware, App, HttpServer};
+use actix_cors::Cors;
+use actix_web::http::header;
+use actix_web::{middleware, web, App, HttpServer};
+use deadpool::Runtime;
 use diesel::prelude::*;
-use diesel::r2d2::{self, ConnectionManager};
+use std::io;
+use std::time::Duration;
 
+#[cfg(all(test, feature = "sqlite"))]
+#[macro_use]
+mod test_utils;
+
+mod auth;
+mod cache;
+mod config;
+mod db;
+mod diff;
 mod errors;
-mod models;
+mod feeds;
+mod markdown;
+mod migrations;
+pub mod models;
+mod openapi;
+mod rate_limit;
 mod routes;
 mod schema;
+mod search;
+mod spam;
+mod validation;
 
-type Pool = r2d2::Pool<ConnectionManager<SqliteConnection>>;
+pub use auth::hash_password;
+pub use config::{BlogConfig, ConfigError, LimitsConfig, PoolConfig, RateLimitConfig};
+pub use db::{establish, DbConnection};
+pub use errors::AppError;
+pub use migrations::MigrationError;
+pub use validation::{password as validate_password, username as validate_username};
+
+type Pool = diesel_async::pooled_connection::deadpool::Pool<DbConnection>;
+
+/// Registers the shared data and every route. `Blog::run` adds the middleware
+/// around it; the tests use it as is, without binding a port.
+fn configure_app(
+    cfg: &mut web::ServiceConfig,
+    pool: Pool,
+    secret: auth::Secret,
+    cache: cache::ListingCache,
+    limiter: rate_limit::RateLimiter,
+    site_url: feeds::SiteUrl,
+    limits: &LimitsConfig,
+) {
+    cfg.app_data(web::Data::new(pool))
+        .app_data(web::Data::new(secret))
+        .app_data(web::Data::new(site_url))
+        .app_data(web::Data::new(cache))
+        .app_data(web::Data::new(limiter))
+        .service(
+            web::scope("/api")
+                .configure(|cfg| routes::comments::configure(cfg, limits))
+                .configure(|cfg| routes::posts::configure(cfg, limits))
+                .configure(routes::revisions::configure)
+                .configure(routes::search::configure)
+                .configure(|cfg| routes::sessions::configure(cfg, limits))
+                .configure(|cfg| routes::tags::configure(cfg, limits))
+                .configure(|cfg| routes::users::configure(cfg, limits)),
+        );
+    routes::docs::configure(cfg);
+    routes::feeds::configure(cfg);
+    routes::health::configure(cfg);
+    routes::pages::configure(cfg);
+}
+
+/// Publishes the scheduled posts as they come due, for as long as the server runs.
+async fn publish_scheduled_posts(pool: Pool, cache: cache::ListingCache, period: Duration) {
+    let mut interval = actix_web::rt::time::interval(period);
+    loop {
+        interval.tick().await;
+        let published = match pool.get().await {
+            Ok(mut connection) => models::publish_scheduled_posts(&mut connection).await,
+            Err(err) => Err(err.into()),
+        };
+        match published {
+            Ok(0) => (),
+            Ok(_) => cache.invalidate(),
+            Err(err) => log::error!("Failed to publish the scheduled posts: {}", err),
+        }
+    }
+}
 
 pub struct Blog {
-    port: u16,
+    config: BlogConfig,
 }
 
 impl Blog {
-    pub fn new(port: u16) -> Self {
-        Blog { port }
+    pub fn new(config: BlogConfig) -> Self {
+        Blog { config }
+    }
+
+    /// Applies the pending migrations to the configured database.
+    pub fn migrate(&self) -> Result<usize, MigrationError> {
+        let mut connection = db::SyncConnection::establish(&self.config.database_url)?;
+        migrations::run(&mut connection)
     }
 
-    pub fn run(&self, database_url: String) -> std::io::Result<()> {
-        let manager = ConnectionManager::<SqliteConnection>::new(database_url);
-        let pool = r2d2::Pool::builder()
-            .build(manager)
+    /// Fails unless the configured database has every embedded migration applied.
+    pub fn check_migrations(&self) -> Result<(), MigrationError> {
+        let mut connection = db::SyncConnection::establish(&self.config.database_url)?;
+        migrations::check(&mut connection)
+    }
+
+    pub async fn run(&self) -> io::Result<()> {
+        let config = self.config.clone();
+
+        {
+            // Migrations only run on a blocking connection, before any request is served.
+            let mut connection = db::SyncConnection::establish(&config.database_url)
+                .map_err(io::Error::other)?;
+            let migrated = if config.migrate_on_start {
+                migrations::run(&mut connection).map(|_| ())
+            } else {
+                migrations::check(&mut connection)
+            };
+            migrated.map_err(io::Error::other)?;
+        }
+
+        let pool = db::pool_builder(&config.database_url)
+            .max_size(config.pool.max_size as usize)
+            .wait_timeout(Some(config.pool.connection_timeout()))
+            .runtime(Runtime::Tokio1)
+            .build()
             .expect("Failed to create connection pool");
 
-        println!("Starting http server: localhost:{}", self.port);
+        {
+            let mut connection = pool.get().await.expect("Failed to get a database connection");
+            models::render_missing_html(&mut connection)
+                .await
+                .expect("Failed to render stored post and comment bodies");
+        }
+
+        let secret = auth::Secret(config.secret_key.clone());
+        let site_url = feeds::SiteUrl(config.site_url.trim_end_matches('/').to_string());
+        let cache = cache::ListingCache::default();
+        let limiter = rate_limit::RateLimiter::default();
+        actix_web::rt::spawn(rate_limit::sweep_buckets(limiter.clone(), rate_limit::SWEEP_PERIOD));
+        actix_web::rt::spawn(publish_scheduled_posts(pool.clone(), cache.clone(), config.publish_interval()));
+        let address = (config.bind_address.clone(), config.port);
+        let workers = config.workers;
+
+        println!("Starting http server: {}:{}", address.0, address.1);
+
+        let mut server = HttpServer::new(move || {
+            let cors = config
+                .cors_origins
+                .iter()
+                .fold(Cors::default(), |cors, origin| cors.allowed_origin(origin))
+                .allowed_methods(vec!["GET", "POST", "PATCH", "DELETE"])
+                .allowed_headers(vec![header::AUTHORIZATION, header::ACCEPT, header::CONTENT_TYPE])
+                .max_age(3600);
 
-        HttpServer::new(move || {
             App::new()
-                .data(pool.clone())
-                .wrap(middleware::Logger::default())
-                .configure(routes::comments::configure)
-                .configure(routes::posts::configure)
-                .configure(routes::users::configure)
-        })
-        .bind(("127.0.0.1", self.port))?
-        .run()
+                .wrap(middleware::Condition::new(!config.cors_origins.is_empty(), cors))
+                .wrap(middleware::Logger::new(config.log_format.as_str()))
+                .configure(|cfg| configure_app(cfg, pool.clone(), secret.clone(), cache.clone(), limiter.clone(), site_url.clone(), &config.limits))
+        });
+        if let Some(workers) = workers {
+            server = server.workers(workers);
+        }
+
+        server.bind(address)?.run().await
+    }
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use super::*;
+    use crate::test_utils::{create_post, create_user, test_pool};
+    use chrono::Utc;
+
+    /// Gives the scheduler a moment to publish the post. The queries run on
+    /// blocking threads, which keep the paused clock from moving meanwhile.
+    async fn is_published(pool: &Pool, post_id: i32) -> bool {
+        for _ in 0..50 {
+            tokio::task::yield_now().await;
+            if models::find_post(&mut pool.get().await.unwrap(), post_id).await.unwrap().published {
+                return true;
+            }
+        }
+        false
+    }
+
+    #[actix_web::test]
+    async fn scheduler_publishes_due_posts_every_period() {
+        let pool = test_pool();
+        let (alice, _) = create_user(&pool, "alice").await;
+        let first = create_post(&pool, &alice, "First", false).await;
+        let second = create_post(&pool, &alice, "Second", false).await;
+        let schedule = |post_id| {
+            let pool = pool.clone();
+            async move {
+                let changes = models::PostChanges {
+                    publish_at: Some(Some(Utc::now().naive_utc())),
+                    ..Default::default()
+                };
+                models::update_post(&mut pool.get().await.unwrap(), post_id, &changes).await.unwrap();
+            }
+        };
+
+        tokio::time::pause();
+        schedule(first.id).await;
+        actix_web::rt::spawn(publish_scheduled_posts(pool.clone(), cache::ListingCache::default(), Duration::from_secs(30)));
+        // Lets the task start its interval, whose first tick is immediate
+        // but for the millisecond timers round up to.
+        tokio::task::yield_now().await;
+        tokio::time::advance(Duration::from_millis(1)).await;
+        assert!(is_published(&pool, first.id).await);
+
+        schedule(second.id).await;
+        tokio::time::advance(Duration::from_secs(29)).await;
+        assert!(!is_published(&pool, second.id).await);
+        tokio::time::advance(Duration::from_secs(1)).await;
+        assert!(is_published(&pool, second.id).await);
     }
 }
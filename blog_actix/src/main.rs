@@ -1,12 +1,60 @@
+use blog_actix::{Blog, BlogConfig};
 use dotenv::dotenv;
+use env_logger::Env;
 use std::env;
+use std::process;
 
-fn main() -> std::io::Result<()> {
+const DEFAULT_LOG_FILTER: &str = "actix_web=info,blog_actix=info";
+const USAGE: &str = "Usage: blog_actix [--config <path>] [--print-config] [migrate]";
+
+#[actix_web::main]
+async fn main() -> std::io::Result<()> {
     dotenv().ok();
-    env::set_var("RUST_LOG", "actix_web=info");
-    env_logger::init();
 
-    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    let app = blog_actix::Blog::new(8080);
-    app.run(database_url)
+    let mut config_path = None;
+    let mut print_config = false;
+    let mut migrate = false;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" => match args.next() {
+                Some(path) => config_path = Some(path),
+                None => exit_with(USAGE),
+            },
+            "--print-config" => print_config = true,
+            "migrate" => migrate = true,
+            _ => exit_with(USAGE),
+        }
+    }
+
+    let config = match BlogConfig::load(config_path.as_deref()) {
+        Ok(config) => config,
+        Err(err) => exit_with(&err.to_string()),
+    };
+    if print_config {
+        print!("{}", config.to_toml());
+        return Ok(());
+    }
+
+    // RUST_LOG, when set, replaces the default of the request log and the blog's own messages.
+    env_logger::Builder::from_env(Env::default().default_filter_or(DEFAULT_LOG_FILTER)).init();
+
+    let blog = Blog::new(config);
+    if migrate {
+        match blog.migrate() {
+            Ok(count) => println!("Applied {} migration(s)", count),
+            Err(err) => exit_with(&err.to_string()),
+        }
+        return Ok(());
+    }
+
+    if let Err(err) = blog.run().await {
+        exit_with(&err.to_string());
+    }
+    Ok(())
+}
+
+fn exit_with(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(2)
 }
@@ -0,0 +1,35 @@
+use pulldown_cmark::{html, Options, Parser};
+use std::collections::HashSet;
+
+const COMMENT_TAGS: [&str; 11] = ["a", "blockquote", "br", "code", "em", "li", "ol", "p", "pre", "strong", "ul"];
+/// Link and image schemes kept, narrower than ammonia's default list.
+const URL_SCHEMES: [&str; 3] = ["http", "https", "mailto"];
+
+fn to_html(source: &str) -> String {
+    let options = Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH;
+    let mut output = String::new();
+    html::push_html(&mut output, Parser::new_ext(source, options));
+
+    output
+}
+
+/// Renders a post body, keeping ammonia's default tag allowlist which already
+/// drops scripts and event handler attributes, and only http(s)/mailto urls.
+pub fn render_post(source: &str) -> String {
+    ammonia::Builder::default()
+        .url_schemes(URL_SCHEMES.iter().cloned().collect())
+        .clean(&to_html(source))
+        .to_string()
+}
+
+/// Renders a comment body, only keeping basic text formatting and links.
+pub fn render_comment(source: &str) -> String {
+    let tags: HashSet<&str> = COMMENT_TAGS.iter().cloned().collect();
+
+    ammonia::Builder::default()
+        .tags(tags)
+        .url_schemes(URL_SCHEMES.iter().cloned().collect())
+        .link_rel(Some("nofollow noopener noreferrer"))
+        .clean(&to_html(source))
+        .to_string()
+}
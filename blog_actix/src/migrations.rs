@@ -0,0 +1,103 @@
+use crate::db::{DbBackend, SyncConnection};
+use diesel::migration::MigrationSource;
+use diesel::prelude::*;
+use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
+use std::error::Error;
+use std::fmt;
+
+#[cfg(feature = "sqlite")]
+const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations");
+#[cfg(feature = "postgres")]
+const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations_postgres");
+
+type BoxError = Box<dyn Error + Send + Sync>;
+
+#[derive(Debug)]
+pub enum MigrationError {
+    Connection(ConnectionError),
+    Database(BoxError),
+    Migration(BoxError),
+    SchemaTooNew(String),
+    Pending(usize),
+}
+
+impl fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MigrationError::Connection(err) => write!(f, "Cannot open the database: {}", err),
+            MigrationError::Database(err) => write!(f, "Cannot read the applied migrations: {}", err),
+            MigrationError::Migration(err) => write!(f, "Migration failed: {}", err),
+            MigrationError::SchemaTooNew(version) => write!(
+                f,
+                "The database has migration {} which this binary doesn't know about, refusing to start with an older release",
+                version
+            ),
+            MigrationError::Pending(count) => {
+                write!(f, "{} migration(s) pending, run `blog_actix migrate` first", count)
+            }
+        }
+    }
+}
+
+impl std::error::Error for MigrationError {}
+
+impl From<ConnectionError> for MigrationError {
+    fn from(e: ConnectionError) -> Self {
+        MigrationError::Connection(e)
+    }
+}
+
+/// Number of embedded migrations not applied yet. Fails if the database has
+/// migrations this binary doesn't embed, i.e. a newer release migrated it.
+pub fn pending(connection: &mut SyncConnection) -> Result<usize, MigrationError> {
+    let applied: Vec<String> = connection
+        .applied_migrations()
+        .map_err(MigrationError::Database)?
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+    let embedded: Vec<String> = MigrationSource::<DbBackend>::migrations(&MIGRATIONS)
+        .map_err(MigrationError::Migration)?
+        .iter()
+        .map(|migration| migration.name().version().to_string())
+        .collect();
+
+    if let Some(unknown) = applied.iter().filter(|v| !embedded.contains(v)).max() {
+        return Err(MigrationError::SchemaTooNew(unknown.clone()));
+    }
+
+    Ok(embedded.iter().filter(|v| !applied.contains(v)).count())
+}
+
+/// Fails with `MigrationError::Pending` unless every embedded migration is applied.
+pub fn check(connection: &mut SyncConnection) -> Result<(), MigrationError> {
+    match pending(connection)? {
+        0 => Ok(()),
+        count => Err(MigrationError::Pending(count)),
+    }
+}
+
+/// Applies the pending embedded migrations, logging each one.
+pub fn run(connection: &mut SyncConnection) -> Result<usize, MigrationError> {
+    pending(connection)?;
+    let migrations = connection.pending_migrations(MIGRATIONS).map_err(MigrationError::Migration)?;
+    for migration in &migrations {
+        log::info!("Running migration {}", migration.name());
+        connection.run_migration(migration).map_err(MigrationError::Migration)?;
+    }
+
+    Ok(migrations.len())
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nothing_is_pending_after_a_run() {
+        let mut connection = SyncConnection::establish(":memory:").unwrap();
+        assert!(run(&mut connection).unwrap() > 0);
+        assert_eq!(pending(&mut connection).unwrap(), 0);
+        assert_eq!(run(&mut connection).unwrap(), 0);
+    }
+}
@@ -1,137 +1,862 @@
+use crate::db::{DbBackend, DbConnection};
 use crate::errors::AppError;
-use crate::schema::{users, posts, comments};
+use crate::markdown;
+use crate::spam;
+use crate::schema::{users, posts, comments, post_revisions, post_tags, tags};
+use chrono::{NaiveDateTime, Utc};
+use diesel::backend::Backend;
+use diesel::deserialize::{self, FromSql};
 use diesel::prelude::*;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::{BigInt, Integer, Text};
+#[cfg(feature = "sqlite")]
+use diesel::connection::AnsiTransactionManager;
+use diesel_async::scoped_futures::{ScopedBoxFuture, ScopedFutureExt};
+#[cfg(feature = "sqlite")]
+use diesel_async::TransactionManager;
+use diesel_async::{AsyncConnection, RunQueryDsl};
+use std::collections::{HashMap, HashSet};
 
 type Result<T> = std::result::Result<T, AppError>;
 
-#[derive(Queryable, Identifiable, Serialize, Debug, PartialEq)]
+#[cfg(feature = "sqlite")]
+define_sql_function!(fn last_insert_rowid() -> Integer);
+
+/// Id of the last row inserted through `connection`. Unlike reading back the
+/// newest row, inserts made meanwhile on other connections can't affect it.
+#[cfg(feature = "sqlite")]
+async fn last_insert_id(connection: &mut DbConnection) -> Result<i32> {
+    diesel::select(last_insert_rowid())
+        .get_result(connection)
+        .await
+        .map_err(Into::into)
+}
+
+/// Runs `callback` in a transaction, nested ones in a savepoint. On SQLite the
+/// outermost transaction starts with `BEGIN IMMEDIATE`: in a deferred one, the
+/// full-text search triggers read before writing, and SQLite then fails a
+/// concurrent write at once with "database is locked" instead of waiting.
+async fn transaction<'a, R, F>(connection: &mut DbConnection, callback: F) -> Result<R>
+where
+    F: for<'r> FnOnce(&'r mut DbConnection) -> ScopedBoxFuture<'a, 'r, Result<R>> + Send + 'a,
+    R: Send + 'a,
+{
+    #[cfg(feature = "sqlite")]
+    {
+        type Manager = <DbConnection as AsyncConnection>::TransactionManager;
+
+        if Manager::transaction_manager_status_mut(connection).transaction_depth()?.is_none() {
+            connection
+                .spawn_blocking(|inner| AnsiTransactionManager::begin_transaction_sql(inner, "BEGIN IMMEDIATE"))
+                .await?;
+            return match callback(&mut *connection).await {
+                Ok(value) => {
+                    Manager::commit_transaction(connection).await?;
+                    Ok(value)
+                }
+                Err(err) => {
+                    Manager::rollback_transaction(connection).await?;
+                    Err(err)
+                }
+            };
+        }
+    }
+
+    connection.transaction(callback).await
+}
+
+#[derive(Queryable, Identifiable, Serialize, Clone, Debug, PartialEq)]
 pub struct User {
     pub id: i32,
     pub username: String,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
 }
 
+type UserColumns = (users::id, users::username, users::created_at, users::updated_at);
+
+/// Public user columns, leaving out the password hash.
+pub const USER_COLUMNS: UserColumns = (users::id, users::username, users::created_at, users::updated_at);
+
 pub enum UserKey<'a> {
     Username(&'a str),
     Id(i32),
 }
 
 // Users ///
-pub fn create_user(connection: &SqliteConnection, username: &str) -> Result<User> {
-    connection.transaction(|| {
-        diesel::insert_into(users::table)
-            .values((users::username.eq(username), ))
-            .execute(connection)?;
-
-        users::table
-            .order(users::id.desc())
-            .select((users::id, users::username))
-            .first(connection)
-            .map_err(Into::into)
+pub async fn create_user(connection: &mut DbConnection, username: &str, password_hash: &str) -> Result<User> {
+    let now = Utc::now().naive_utc();
+
+    let insert = diesel::insert_into(users::table).values((
+        users::username.eq(username),
+        users::password_hash.eq(password_hash),
+        users::created_at.eq(now),
+        users::updated_at.eq(now),
+    ));
+
+    #[cfg(feature = "postgres")]
+    let user = insert.returning(USER_COLUMNS).get_result(connection).await?;
+
+    #[cfg(feature = "sqlite")]
+    let user = transaction(connection, |connection| {
+        async move {
+            insert.execute(connection).await?;
+            let id = last_insert_id(connection).await?;
+            find_user(connection, UserKey::Id(id)).await
+        }
+        .scope_boxed()
     })
+    .await?;
+
+    Ok(user)
 }
 
-pub fn find_user<'a>(connection: &SqliteConnection, key: UserKey<'a>) -> Result<User> {
+pub async fn find_user<'a>(connection: &mut DbConnection, key: UserKey<'a>) -> Result<User> {
     match key {
         UserKey::Username(name) => users::table
             .filter(users::username.eq(name))
-            .select((users::id, users::username))
+            .select(USER_COLUMNS)
             .first::<User>(connection)
+            .await
             .map_err(AppError::from),
         UserKey::Id(id) => users::table
             .find(id)
-            .select((users::id, users::username))
+            .select(USER_COLUMNS)
             .first::<User>(connection)
+            .await
             .map_err(Into::into)
     }
 }
 
+pub async fn find_credentials(connection: &mut DbConnection, username: &str) -> Result<(User, String)> {
+    users::table
+        .filter(users::username.eq(username))
+        .select((USER_COLUMNS, users::password_hash))
+        .first::<(User, String)>(connection)
+        .await
+        .map_err(Into::into)
+}
+
+pub async fn all_users(connection: &mut DbConnection) -> Result<Vec<User>> {
+    users::table
+        .order(users::id)
+        .select(USER_COLUMNS)
+        .load(connection)
+        .await
+        .map_err(Into::into)
+}
+
+pub async fn rename_user(connection: &mut DbConnection, user_id: i32, username: &str) -> Result<User> {
+    let now = Utc::now().naive_utc();
+
+    transaction(connection, |connection| {
+        async move {
+            diesel::update(users::table.filter(users::id.eq(user_id)))
+                .set((users::username.eq(username), users::updated_at.eq(now)))
+                .execute(connection)
+                .await?;
+
+            find_user(connection, UserKey::Id(user_id)).await
+        }
+        .scope_boxed()
+    })
+    .await
+}
+
+/// Deletes a user with their posts and comments.
+pub async fn delete_user(connection: &mut DbConnection, user_id: i32) -> Result<User> {
+    transaction(connection, |connection| {
+        async move {
+            let user = find_user(connection, UserKey::Id(user_id)).await?;
+
+            delete_user_comments(connection, user_id).await?;
+            let post_ids = posts::table
+                .filter(posts::user_id.eq(user_id))
+                .select(posts::id)
+                .load::<i32>(connection)
+                .await?;
+            for post_id in post_ids {
+                delete_post(connection, post_id).await?;
+            }
+            diesel::delete(users::table.filter(users::id.eq(user_id)))
+                .execute(connection)
+                .await?;
+
+            Ok(user)
+        }
+        .scope_boxed()
+    })
+    .await
+}
+
 // Posts ///
-#[derive(Queryable, Associations, Identifiable, Serialize, Debug)]
-#[belongs_to(User)]
+#[derive(Queryable, Insertable, Associations, Identifiable, Serialize, Deserialize, Clone, Debug)]
+#[diesel(belongs_to(User))]
 pub struct Post {
     pub id: i32,
     pub user_id: i32,
     pub title: String,
     pub body: String,
     pub published: bool,
+    pub body_html: String,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub published_at: Option<NaiveDateTime>,
+    /// No new comments, from anyone.
+    #[serde(default)]
+    pub comments_closed: bool,
+    /// Comments of other users wait for the author's approval.
+    #[serde(default)]
+    pub comments_moderated: bool,
+    /// When the scheduler publishes the post, if it is still unpublished then.
+    #[serde(default)]
+    pub publish_at: Option<NaiveDateTime>,
 }
 
-pub fn create_post(connection: &SqliteConnection, user: &User, title: &str, body: &str) -> Result<Post> {
-    connection.transaction(|| {
-        diesel::insert_into(posts::table)
-            .values((
-                posts::user_id.eq(user.id),
-                posts::title.eq(title),
-                posts::body.eq(body)
-            ))
-            .execute(connection)?;
-
-        posts::table
-            .order(posts::id.desc())
-            .select(posts::all_columns)
-            .first(connection)
-            .map_err(Into::into)
+/// Orders post listings, newest first.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum PostSort {
+    #[default]
+    Id,
+    CreatedAt,
+    UpdatedAt,
+    PublishedAt,
+}
+
+pub async fn create_post(connection: &mut DbConnection, user: &User, title: &str, body: &str) -> Result<Post> {
+    let now = Utc::now().naive_utc();
+
+    let insert = diesel::insert_into(posts::table).values((
+        posts::user_id.eq(user.id),
+        posts::title.eq(title),
+        posts::body.eq(body),
+        posts::body_html.eq(markdown::render_post(body)),
+        posts::created_at.eq(now),
+        posts::updated_at.eq(now),
+    ));
+
+    transaction(connection, |connection| {
+        async move {
+            #[cfg(feature = "postgres")]
+            let post: Post = insert.get_result(connection).await?;
+
+            #[cfg(feature = "sqlite")]
+            let post = {
+                insert.execute(connection).await?;
+                let id = last_insert_id(connection).await?;
+                find_post(connection, id).await?
+            };
+
+            record_revision(connection, &post).await?;
+            Ok(post)
+        }
+        .scope_boxed()
     })
+    .await
 }
 
-pub fn publish_post(connection: &SqliteConnection, post_id: i32) -> Result<Post> {
-    connection.transaction(|| {
-        diesel::update(posts::table.filter(posts::id.eq(post_id)))
-            .set(posts::published.eq(true))
-            .execute(connection)?;
+pub async fn find_post(connection: &mut DbConnection, post_id: i32) -> Result<Post> {
+    posts::table
+        .find(post_id)
+        .select(posts::all_columns)
+        .first(connection)
+        .await
+        .map_err(Into::into)
+}
 
-        posts::table
-            .find(post_id)
-            .select(posts::all_columns)
-            .first(connection)
-            .map_err(Into::into)
+/// Loads a post the viewer can read: published, or a draft of their own.
+/// Other drafts and scheduled posts are `RecordNotFound`, as if missing.
+pub async fn find_visible_post(connection: &mut DbConnection, post_id: i32, viewer: Option<i32>) -> Result<Post> {
+    let post = find_post(connection, post_id).await?;
+    if !post.published && viewer != Some(post.user_id) {
+        return Err(AppError::RecordNotFound);
+    }
+
+    Ok(post)
+}
+
+pub async fn publish_post(connection: &mut DbConnection, post_id: i32) -> Result<Post> {
+    let now = Utc::now().naive_utc();
+
+    transaction(connection, |connection| {
+        async move {
+            diesel::update(posts::table.filter(posts::id.eq(post_id)))
+                .set((posts::published.eq(true), posts::updated_at.eq(now)))
+                .execute(connection)
+                .await?;
+            diesel::update(posts::table.filter(posts::id.eq(post_id)).filter(posts::published_at.is_null()))
+                .set(posts::published_at.eq(now))
+                .execute(connection)
+                .await?;
+            cancel_schedule(connection, post_id).await?;
+
+            find_post(connection, post_id).await
+        }
+        .scope_boxed()
+    })
+    .await
+}
+
+async fn cancel_schedule(connection: &mut DbConnection, post_id: i32) -> Result<()> {
+    diesel::update(posts::table.filter(posts::id.eq(post_id)))
+        .set(posts::publish_at.eq(None::<NaiveDateTime>))
+        .execute(connection)
+        .await?;
+
+    Ok(())
+}
+
+/// Publishes the posts whose `publish_at` has come, as of that time, and
+/// returns how many there were.
+pub async fn publish_scheduled_posts(connection: &mut DbConnection) -> Result<usize> {
+    let now = Utc::now().naive_utc();
+
+    diesel::update(posts::table.filter(posts::published.eq(false)).filter(posts::publish_at.le(now)))
+        .set((
+            posts::published.eq(true),
+            posts::published_at.eq(posts::publish_at),
+            posts::publish_at.eq(None::<NaiveDateTime>),
+            posts::updated_at.eq(now),
+        ))
+        .execute(connection)
+        .await
+        .map_err(Into::into)
+}
+
+/// Setting `published` without a new `publish_at` cancels the one of the post.
+/// `publish_at: Some(None)` cancels it as well.
+#[derive(AsChangeset, Debug, Default)]
+#[diesel(table_name = posts)]
+pub struct PostChanges<'a> {
+    pub title: Option<&'a str>,
+    pub body: Option<&'a str>,
+    pub published: Option<bool>,
+    pub comments_closed: Option<bool>,
+    pub comments_moderated: Option<bool>,
+    pub publish_at: Option<Option<NaiveDateTime>>,
+}
+
+impl<'a> PostChanges<'a> {
+    fn is_empty(&self) -> bool {
+        self.title.is_none()
+            && self.body.is_none()
+            && self.published.is_none()
+            && self.comments_closed.is_none()
+            && self.comments_moderated.is_none()
+            && self.publish_at.is_none()
+    }
+}
+
+pub async fn update_post(connection: &mut DbConnection, post_id: i32, changes: &PostChanges<'_>) -> Result<Post> {
+    let now = Utc::now().naive_utc();
+
+    transaction(connection, |connection| {
+        async move {
+            if !changes.is_empty() {
+                let body_html = changes.body.map(|body| posts::body_html.eq(markdown::render_post(body)));
+                diesel::update(posts::table.filter(posts::id.eq(post_id)))
+                    .set((changes, body_html, posts::updated_at.eq(now)))
+                    .execute(connection)
+                    .await?;
+            }
+
+            match changes.published {
+                Some(true) => {
+                    diesel::update(posts::table.filter(posts::id.eq(post_id)).filter(posts::published_at.is_null()))
+                        .set(posts::published_at.eq(now))
+                        .execute(connection)
+                        .await?;
+                }
+                Some(false) => {
+                    diesel::update(posts::table.filter(posts::id.eq(post_id)))
+                        .set(posts::published_at.eq(None::<NaiveDateTime>))
+                        .execute(connection)
+                        .await?;
+                }
+                None => (),
+            }
+            if changes.published.is_some() && changes.publish_at.is_none() {
+                cancel_schedule(connection, post_id).await?;
+            }
+
+            let post = find_post(connection, post_id).await?;
+            if changes.title.is_some() || changes.body.is_some() {
+                record_revision(connection, &post).await?;
+            }
+
+            Ok(post)
+        }
+        .scope_boxed()
+    })
+    .await
+}
+
+pub async fn delete_post(connection: &mut DbConnection, post_id: i32) -> Result<Post> {
+    transaction(connection, |connection| {
+        async move {
+            let post = find_post(connection, post_id).await?;
+
+            diesel::delete(comments::table.filter(comments::post_id.eq(post_id)))
+                .execute(connection)
+                .await?;
+            diesel::delete(post_tags::table.filter(post_tags::post_id.eq(post_id)))
+                .execute(connection)
+                .await?;
+            diesel::delete(post_revisions::table.filter(post_revisions::post_id.eq(post_id)))
+                .execute(connection)
+                .await?;
+            diesel::delete(posts::table.filter(posts::id.eq(post_id)))
+                .execute(connection)
+                .await?;
+
+            Ok(post)
+        }
+        .scope_boxed()
     })
+    .await
 }
 
-pub fn all_posts(connection: &SqliteConnection) -> Result<Vec<((Post, User), Vec<(Comment, User)>)>> {
-    let posts_with_user = posts::table
-        .order(posts::id.desc())
+/// Loads a page of `query` in the order requested by `page`, resuming after
+/// the post the cursor points to.
+async fn sorted_posts(connection: &mut DbConnection, mut query: posts::BoxedQuery<'_, DbBackend>, page: &PageRequest)
+    -> Result<Page<Post>> {
+    if let Some(after) = page.after {
+        let anchor = find_post(connection, after).await.map_err(|_| AppError::InvalidPage)?;
+        query = match page.sort {
+            PostSort::Id => query.filter(posts::id.lt(anchor.id)),
+            PostSort::CreatedAt => query.filter(
+                posts::created_at.lt(anchor.created_at)
+                    .or(posts::created_at.eq(anchor.created_at).and(posts::id.lt(anchor.id))),
+            ),
+            PostSort::UpdatedAt => query.filter(
+                posts::updated_at.lt(anchor.updated_at)
+                    .or(posts::updated_at.eq(anchor.updated_at).and(posts::id.lt(anchor.id))),
+            ),
+            // Unpublished posts have no publication date and are listed last.
+            PostSort::PublishedAt => match anchor.published_at {
+                Some(published_at) => query.filter(
+                    posts::published_at.lt(published_at)
+                        .or(posts::published_at.eq(published_at).and(posts::id.lt(anchor.id)))
+                        .or(posts::published_at.is_null()),
+                ),
+                None => query.filter(posts::published_at.is_null().and(posts::id.lt(anchor.id))),
+            },
+        };
+    }
+
+    // SQLite sorts nulls first, so last when descending; Postgres needs to be told.
+    #[cfg(feature = "sqlite")]
+    let published_at = posts::published_at.desc();
+    #[cfg(feature = "postgres")]
+    let published_at = posts::published_at.desc().nulls_last();
+
+    query = match page.sort {
+        PostSort::Id => query.order(posts::id.desc()),
+        PostSort::CreatedAt => query.order((posts::created_at.desc(), posts::id.desc())),
+        PostSort::UpdatedAt => query.order((posts::updated_at.desc(), posts::id.desc())),
+        PostSort::PublishedAt => query.order((published_at, posts::id.desc())),
+    };
+
+    let posts = query.limit(page.limit + 1).load::<Post>(connection).await?;
+    Ok(paginate(posts, page, |post| post.id))
+}
+
+/// Published posts with their authors, comments and tags. `viewer` also sees
+/// the comments waiting for approval on their own posts.
+pub async fn all_posts(connection: &mut DbConnection, page: &PageRequest, tag: Option<&str>, viewer: Option<i32>)
+    -> Result<Page<((Post, User), Vec<(Comment, User)>, Vec<Tag>)>> {
+    let mut query = posts::table
         .filter(posts::published.eq(true))
-        .inner_join(users::table)
-        .select((posts::all_columns, (users::id, users::username)))
-        .load::<(Post,User)>(connection)?;
+        .into_boxed();
+    if let Some(tag) = tag {
+        query = query.filter(posts::id.eq_any(tagged_post_ids(tag)));
+    }
 
-    let (posts, post_users): (Vec<_>, Vec<_>) = posts_with_user.into_iter().unzip();
+    let posts = sorted_posts(connection, query, page).await?;
+    let next = posts.next;
+    let posts = posts.items;
+
+    let user_ids: Vec<i32> = posts.iter().map(|post| post.user_id).collect();
+    let users: HashMap<i32, User> = users::table
+        .filter(users::id.eq_any(user_ids))
+        .select(USER_COLUMNS)
+        .load::<User>(connection)
+        .await?
+        .into_iter()
+        .map(|user| (user.id, user))
+        .collect();
+    let post_users: Vec<User> = posts
+        .iter()
+        .map(|post| users.get(&post.user_id).cloned().ok_or(AppError::RecordNotFound))
+        .collect::<Result<_>>()?;
 
     let comments = Comment::belonging_to(&posts)
+        .filter(comments::status.ne(CommentStatus::Rejected))
         .inner_join(users::table)
-        .select((comments::all_columns, (users::id, users::username)))
-        .load::<(Comment, User)>(connection)?
-        .grouped_by(&posts);
+        .select((comments::all_columns, USER_COLUMNS))
+        .load::<(Comment, User)>(connection)
+        .await?
+        .grouped_by(&posts)
+        .into_iter()
+        .zip(&posts)
+        .map(|(comments, post)| visible_comments(post, comments, viewer))
+        .collect::<Vec<_>>();
+    let tags = tags_by_post(connection, &posts).await?;
 
-    Ok(posts.into_iter().zip(post_users).zip(comments).collect())
+    Ok(Page {
+        items: posts
+            .into_iter()
+            .zip(post_users)
+            .zip(comments)
+            .zip(tags)
+            .map(|((post_with_user, comments), tags)| (post_with_user, comments, tags))
+            .collect(),
+        next,
+    })
 }
 
-pub fn user_posts(connection: &SqliteConnection, user_id: i32) -> Result<Vec<(Post, Vec<(Comment, User)>)>> {
-    let posts = posts::table
+pub async fn user_posts(connection: &mut DbConnection, user_id: i32, page: &PageRequest, tag: Option<&str>, viewer: Option<i32>)
+    -> Result<Page<(Post, Vec<(Comment, User)>, Vec<Tag>)>> {
+    let mut query = posts::table
         .filter(posts::user_id.eq(user_id))
-        .order(posts::id.desc())
-        .select(posts::all_columns)
-        .load::<Post>(connection)?;
+        .into_boxed();
+    // Drafts are filtered before the limit, so that pages aren't short of posts.
+    if viewer != Some(user_id) {
+        query = query.filter(posts::published.eq(true));
+    }
+    if let Some(tag) = tag {
+        query = query.filter(posts::id.eq_any(tagged_post_ids(tag)));
+    }
+
+    let posts = sorted_posts(connection, query, page).await?;
+    let next = posts.next;
+    let posts = posts.items;
 
     let comments = Comment::belonging_to(&posts)
+        .filter(comments::status.ne(CommentStatus::Rejected))
         .inner_join(users::table)
-        .select((comments::all_columns, (users::id, users::username)))
-        .load::<(Comment, User)>(connection)?
-        .grouped_by(&posts);
+        .select((comments::all_columns, USER_COLUMNS))
+        .load::<(Comment, User)>(connection)
+        .await?
+        .grouped_by(&posts)
+        .into_iter()
+        .zip(&posts)
+        .map(|(comments, post)| visible_comments(post, comments, viewer))
+        .collect::<Vec<_>>();
+    let tags = tags_by_post(connection, &posts).await?;
 
-    Ok(posts.into_iter().zip(comments).collect())
+    Ok(Page {
+        items: posts
+            .into_iter()
+            .zip(comments)
+            .zip(tags)
+            .map(|((post, comments), tags)| (post, comments, tags))
+            .collect(),
+        next,
+    })
+}
+
+/// Latest published posts, optionally of a single author, for feeds.
+pub async fn published_posts(connection: &mut DbConnection, user_id: Option<i32>, limit: i64) -> Result<Vec<(Post, User)>> {
+    let mut query = posts::table
+        .filter(posts::published.eq(true))
+        .order((posts::published_at.desc(), posts::id.desc()))
+        .inner_join(users::table)
+        .select((posts::all_columns, USER_COLUMNS))
+        .limit(limit)
+        .into_boxed();
+    if let Some(user_id) = user_id {
+        query = query.filter(posts::user_id.eq(user_id));
+    }
+
+    query.load::<(Post, User)>(connection).await.map_err(Into::into)
+}
+
+// Revisions ///
+/// The title and body of a post as saved by one edit.
+#[derive(Queryable, Insertable, Associations, Identifiable, Serialize, Deserialize, Clone, Debug)]
+#[diesel(belongs_to(Post))]
+pub struct PostRevision {
+    pub id: i32,
+    pub post_id: i32,
+    pub title: String,
+    pub body: String,
+    pub created_at: NaiveDateTime,
+}
+
+/// The revision with the current title and body of a post, unless it was
+/// imported from a dump without revisions and not edited since.
+pub async fn latest_revision(connection: &mut DbConnection, post_id: i32) -> Result<Option<PostRevision>> {
+    post_revisions::table
+        .filter(post_revisions::post_id.eq(post_id))
+        .order(post_revisions::id.desc())
+        .first(connection)
+        .await
+        .optional()
+        .map_err(Into::into)
+}
+
+/// Saves the current title and body of `post` as a revision, unless the
+/// latest one already has them.
+async fn record_revision(connection: &mut DbConnection, post: &Post) -> Result<()> {
+    let latest = latest_revision(connection, post.id).await?;
+    if latest.is_some_and(|latest| latest.title == post.title && latest.body == post.body) {
+        return Ok(());
+    }
+
+    diesel::insert_into(post_revisions::table)
+        .values((
+            post_revisions::post_id.eq(post.id),
+            post_revisions::title.eq(&post.title),
+            post_revisions::body.eq(&post.body),
+            post_revisions::created_at.eq(post.updated_at),
+        ))
+        .execute(connection)
+        .await?;
+
+    Ok(())
+}
+
+/// The revisions of a post, newest first: the first one has its current title and body.
+pub async fn post_revisions(connection: &mut DbConnection, post_id: i32, page: &PageRequest) -> Result<Page<PostRevision>> {
+    let mut query = post_revisions::table
+        .filter(post_revisions::post_id.eq(post_id))
+        .order(post_revisions::id.desc())
+        .limit(page.limit + 1)
+        .into_boxed();
+    if let Some(after) = page.after {
+        query = query.filter(post_revisions::id.lt(after));
+    }
+
+    let revisions = query.load::<PostRevision>(connection).await?;
+    Ok(paginate(revisions, page, |revision| revision.id))
+}
+
+pub async fn find_revision(connection: &mut DbConnection, post_id: i32, revision_id: i32) -> Result<PostRevision> {
+    post_revisions::table
+        .find(revision_id)
+        .filter(post_revisions::post_id.eq(post_id))
+        .first(connection)
+        .await
+        .map_err(Into::into)
+}
+
+/// Sets the title and body of a post back to those of a revision, which
+/// records them again as the newest revision.
+pub async fn restore_revision(connection: &mut DbConnection, post_id: i32, revision_id: i32) -> Result<Post> {
+    let revision = find_revision(connection, post_id, revision_id).await?;
+    let changes = PostChanges {
+        title: Some(revision.title.as_str()),
+        body: Some(revision.body.as_str()),
+        ..Default::default()
+    };
+
+    update_post(connection, post_id, &changes).await
+}
+
+// Tags ///
+#[derive(Queryable, Insertable, Identifiable, Serialize, Deserialize, Clone, Debug)]
+pub struct Tag {
+    pub id: i32,
+    pub name: String,
+}
+
+#[derive(Queryable, Insertable, Associations, Identifiable, Serialize, Deserialize, Debug)]
+#[diesel(belongs_to(Post))]
+#[diesel(belongs_to(Tag))]
+#[diesel(primary_key(post_id, tag_id))]
+#[diesel(table_name = post_tags)]
+pub struct PostTag {
+    pub post_id: i32,
+    pub tag_id: i32,
+}
+
+#[derive(QueryableByName, Serialize, Debug)]
+pub struct TagCount {
+    #[diesel(sql_type = Integer)]
+    pub id: i32,
+    #[diesel(sql_type = Text)]
+    pub name: String,
+    #[diesel(sql_type = BigInt)]
+    pub post_count: i64,
+}
+
+fn tagged_post_ids(tag: &str) -> post_tags::BoxedQuery<'_, DbBackend, Integer> {
+    let tag_ids = tags::table.filter(tags::name.eq(tag)).select(tags::id);
+
+    post_tags::table
+        .filter(post_tags::tag_id.eq_any(tag_ids))
+        .select(post_tags::post_id)
+        .into_boxed()
+}
+
+async fn tags_by_post(connection: &mut DbConnection, posts: &[Post]) -> Result<Vec<Vec<Tag>>> {
+    let tags = PostTag::belonging_to(posts)
+        .inner_join(tags::table)
+        .order(tags::name.asc())
+        .select(((post_tags::post_id, post_tags::tag_id), (tags::id, tags::name)))
+        .load::<(PostTag, Tag)>(connection)
+        .await?
+        .grouped_by(posts)
+        .into_iter()
+        .map(|tags| tags.into_iter().map(|(_, tag)| tag).collect())
+        .collect();
+
+    Ok(tags)
+}
+
+pub async fn find_post_tags(connection: &mut DbConnection, post_id: i32) -> Result<Vec<Tag>> {
+    post_tags::table
+        .filter(post_tags::post_id.eq(post_id))
+        .inner_join(tags::table)
+        .order(tags::name.asc())
+        .select((tags::id, tags::name))
+        .load::<Tag>(connection)
+        .await
+        .map_err(Into::into)
+}
+
+pub async fn tag_post(connection: &mut DbConnection, post_id: i32, name: &str) -> Result<Vec<Tag>> {
+    transaction(connection, |connection| {
+        async move {
+            #[cfg(feature = "sqlite")]
+            let insert_tag = diesel::insert_or_ignore_into(tags::table).values(tags::name.eq(name));
+            #[cfg(feature = "postgres")]
+            let insert_tag = diesel::insert_into(tags::table).values(tags::name.eq(name)).on_conflict_do_nothing();
+            insert_tag.execute(connection).await?;
+
+            let tag_id = tags::table
+                .filter(tags::name.eq(name))
+                .select(tags::id)
+                .first::<i32>(connection)
+                .await?;
+
+            let values = (post_tags::post_id.eq(post_id), post_tags::tag_id.eq(tag_id));
+            #[cfg(feature = "sqlite")]
+            let insert_post_tag = diesel::insert_or_ignore_into(post_tags::table).values(values);
+            #[cfg(feature = "postgres")]
+            let insert_post_tag = diesel::insert_into(post_tags::table).values(values).on_conflict_do_nothing();
+            insert_post_tag.execute(connection).await?;
+
+            find_post_tags(connection, post_id).await
+        }
+        .scope_boxed()
+    })
+    .await
+}
+
+pub async fn untag_post(connection: &mut DbConnection, post_id: i32, name: &str) -> Result<Vec<Tag>> {
+    transaction(connection, |connection| {
+        async move {
+            let tag_ids = tags::table.filter(tags::name.eq(name)).select(tags::id);
+            diesel::delete(
+                post_tags::table
+                    .filter(post_tags::post_id.eq(post_id))
+                    .filter(post_tags::tag_id.eq_any(tag_ids)),
+            )
+            .execute(connection)
+            .await?;
+
+            find_post_tags(connection, post_id).await
+        }
+        .scope_boxed()
+    })
+    .await
+}
+
+/// Every tag with the number of published posts using it.
+pub async fn all_tags(connection: &mut DbConnection) -> Result<Vec<TagCount>> {
+    diesel::sql_query(
+        "SELECT tags.id, tags.name, COUNT(posts.id) AS post_count FROM tags \
+         LEFT JOIN post_tags ON post_tags.tag_id = tags.id \
+         LEFT JOIN posts ON posts.id = post_tags.post_id AND posts.published \
+         GROUP BY tags.id, tags.name \
+         ORDER BY tags.name",
+    )
+    .load::<TagCount>(connection)
+    .await
+    .map_err(Into::into)
 }
 
 // Comments ///
-#[derive(Queryable, Associations, Identifiable, Serialize, Debug)]
-#[belongs_to(User)]
-#[belongs_to(Post)]
+/// Moderation state of a comment. Only approved comments are listed, except
+/// to the author of the post, who also sees the pending ones.
+#[derive(AsExpression, FromSqlRow, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "snake_case")]
+pub enum CommentStatus {
+    Pending,
+    #[default]
+    Approved,
+    Rejected,
+}
+
+impl CommentStatus {
+    fn as_str(&self) -> &'static str {
+        match self {
+            CommentStatus::Pending => "pending",
+            CommentStatus::Approved => "approved",
+            CommentStatus::Rejected => "rejected",
+        }
+    }
+}
+
+impl ToSql<Text, DbBackend> for CommentStatus {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, DbBackend>) -> serialize::Result {
+        <str as ToSql<Text, DbBackend>>::to_sql(self.as_str(), out)
+    }
+}
+
+impl FromSql<Text, DbBackend> for CommentStatus {
+    fn from_sql(bytes: <DbBackend as Backend>::RawValue<'_>) -> deserialize::Result<Self> {
+        match <String as FromSql<Text, DbBackend>>::from_sql(bytes)?.as_str() {
+            "pending" => Ok(CommentStatus::Pending),
+            "approved" => Ok(CommentStatus::Approved),
+            "rejected" => Ok(CommentStatus::Rejected),
+            status => Err(format!("Unknown comment status: {}", status).into()),
+        }
+    }
+}
+
+/// Statuses of the comments of a post by `author_id` that `viewer` may see.
+fn visible_statuses(author_id: i32, viewer: Option<i32>) -> Vec<CommentStatus> {
+    if viewer == Some(author_id) {
+        vec![CommentStatus::Approved, CommentStatus::Pending]
+    } else {
+        vec![CommentStatus::Approved]
+    }
+}
+
+fn visible_comments(post: &Post, mut comments: Vec<(Comment, User)>, viewer: Option<i32>) -> Vec<(Comment, User)> {
+    let statuses = visible_statuses(post.user_id, viewer);
+    comments.retain(|(comment, _)| statuses.contains(&comment.status));
+    comments
+}
+
+#[derive(Queryable, Insertable, Associations, Identifiable, Serialize, Deserialize, Debug)]
+#[diesel(belongs_to(User))]
+#[diesel(belongs_to(Post))]
 pub struct Comment {
     pub id: i32,
     pub user_id: i32,
     pub post_id: i32,
     pub body: String,
+    pub parent_id: Option<i32>,
+    pub body_html: String,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    #[serde(default)]
+    pub status: CommentStatus,
+}
+
+#[derive(Serialize, Debug)]
+pub struct CommentThread {
+    pub comment: Comment,
+    pub user: User,
+    pub replies: Vec<CommentThread>,
+    /// Set when replies to this comment were left out by the thread limits.
+    pub has_more_replies: bool,
 }
 
 #[derive(Queryable, Serialize, Debug)]
@@ -141,38 +866,472 @@ pub struct PostWithComment {
     pub published: bool,
 }
 
-pub fn create_comment(connection: &SqliteConnection, user_id: i32, post_id: i32, body: &str) -> Result<Comment> {
-    connection.transaction(|| {
-        diesel::insert_into(comments::table)
-            .values((
-                comments::user_id.eq(user_id),
+pub async fn create_comment(connection: &mut DbConnection, user: &User, post_id: i32, parent_id: Option<i32>, body: &str)
+    -> Result<Comment> {
+    let now = Utc::now().naive_utc();
+
+    transaction(connection, |connection| {
+        async move {
+            let post = find_post(connection, post_id).await?;
+            if !post.published && post.user_id != user.id {
+                return Err(AppError::RecordNotFound);
+            }
+            if post.comments_closed {
+                return Err(AppError::CommentsClosed);
+            }
+
+            if let Some(parent_id) = parent_id {
+                let parent_post_id = comments::table
+                    .find(parent_id)
+                    .filter(comments::status.eq(CommentStatus::Approved))
+                    .select(comments::post_id)
+                    .first::<i32>(connection)
+                    .await
+                    .optional()?;
+                if parent_post_id != Some(post_id) {
+                    return Err(AppError::InvalidParent);
+                }
+            }
+
+            let duplicate = comments::table
+                .filter(comments::user_id.eq(user.id))
+                .filter(comments::created_at.gt(now - spam::duplicate_window()))
+                .filter(comments::body.eq(body))
+                .select(comments::id)
+                .first::<i32>(connection)
+                .await
+                .optional()?
+                .is_some();
+            let status = if spam::is_suspicious(body, duplicate) || (post.comments_moderated && post.user_id != user.id) {
+                CommentStatus::Pending
+            } else {
+                CommentStatus::Approved
+            };
+
+            let insert = diesel::insert_into(comments::table).values((
+                comments::user_id.eq(user.id),
                 comments::post_id.eq(post_id),
                 comments::body.eq(body),
-            ))
-            .execute(connection)?;
+                comments::body_html.eq(markdown::render_comment(body)),
+                comments::parent_id.eq(parent_id),
+                comments::created_at.eq(now),
+                comments::updated_at.eq(now),
+                comments::status.eq(status),
+            ));
 
-        comments::table
-            .order(comments::id.desc())
-            .select(comments::all_columns)
-            .first(connection)
-            .map_err(Into::into)
+            #[cfg(feature = "postgres")]
+            let comment = insert.get_result(connection).await?;
+
+            #[cfg(feature = "sqlite")]
+            let comment = {
+                insert.execute(connection).await?;
+
+                comments::table
+                    .find(last_insert_id(connection).await?)
+                    .select(comments::all_columns)
+                    .first(connection)
+                    .await?
+            };
+
+            Ok(comment)
+        }
+        .scope_boxed()
     })
+    .await
 }
 
-pub fn post_comments(connection: &SqliteConnection, post_id: i32) -> Result<Vec<(Comment, User)>> {
-    comments::table
-        .filter(comments::post_id.eq(post_id))
+/// Levels of replies loaded below a top level comment, deeper ones are left out.
+pub(crate) const MAX_REPLY_LEVELS: usize = 50;
+/// Replies loaded for a page of top level comments, the later ones are left out.
+pub(crate) const MAX_THREAD_REPLIES: usize = 1000;
+
+/// Top level comments of a post with their replies nested up to `max_depth` levels;
+/// deeper replies are listed flat under the comment at the last level. Comments whose
+/// replies were cut by `MAX_REPLY_LEVELS` or `MAX_THREAD_REPLIES` have `has_more_replies` set.
+pub async fn post_comments(connection: &mut DbConnection, post_id: i32, page: &PageRequest, max_depth: usize, viewer: Option<i32>)
+    -> Result<Page<CommentThread>> {
+    // On SQLite every query is a hop to a blocking thread, so the post is joined
+    // to its comments rather than looked up first, and each comment says whether
+    // it has replies, which saves the query for a level that has none.
+    let replies_alias = diesel::alias!(comments as replies);
+    let has_replies = diesel::dsl::exists(
+        replies_alias
+            .filter(replies_alias.field(comments::parent_id).eq(comments::id.nullable()))
+            .select(replies_alias.field(comments::id)),
+    );
+
+    let mut query = comments::table
         .inner_join(users::table)
-        .select((comments::all_columns, (users::id, users::username)))
-        .load::<(Comment, User)>(connection)
-        .map_err(Into::into)
+        .inner_join(posts::table)
+        .filter(comments::post_id.eq(post_id))
+        .filter(comments::parent_id.is_null())
+        .order(comments::id.asc())
+        .select((comments::all_columns, USER_COLUMNS, posts::user_id, has_replies))
+        .limit(page.limit + 1)
+        .into_boxed();
+    query = match viewer {
+        Some(viewer) => query
+            .filter(posts::published.eq(true).or(posts::user_id.eq(viewer)))
+            .filter(
+                comments::status
+                    .eq(CommentStatus::Approved)
+                    .or(comments::status.eq(CommentStatus::Pending).and(posts::user_id.eq(viewer))),
+            ),
+        None => query
+            .filter(posts::published.eq(true))
+            .filter(comments::status.eq(CommentStatus::Approved)),
+    };
+    if let Some(after) = page.after {
+        query = query.filter(comments::id.gt(after));
+    }
+
+    let roots = query.load::<(Comment, User, i32, bool)>(connection).await?;
+    let author_id = match roots.as_slice() {
+        [(_, _, author_id, _), ..] => *author_id,
+        [] => {
+            // No comments to show, or no post to show them for.
+            find_visible_post(connection, post_id, viewer).await?;
+            return Ok(Page { items: Vec::new(), next: None });
+        }
+    };
+    let statuses = visible_statuses(author_id, viewer);
+    let roots = paginate(roots, page, |(comment, ..)| comment.id);
+
+    // Only the replies to this page's comments, one level at a time.
+    let mut replies: HashMap<i32, Vec<(Comment, User)>> = HashMap::new();
+    let mut parents: Vec<i32> = roots.items.iter().filter(|row| row.3).map(|(comment, ..)| comment.id).collect();
+    let mut loaded = 0;
+    // Comments whose replies were not all loaded, less the replies that were.
+    let mut unexplored: (Vec<i32>, Vec<i32>) = (Vec::new(), Vec::new());
+    for levels in 0..=MAX_REPLY_LEVELS {
+        if parents.is_empty() {
+            break;
+        }
+        if levels == MAX_REPLY_LEVELS {
+            unexplored = (parents, Vec::new());
+            break;
+        }
+        let remaining = MAX_THREAD_REPLIES - loaded;
+        let mut level = comments::table
+            .filter(comments::parent_id.eq_any(&parents))
+            .filter(comments::status.eq_any(statuses.clone()))
+            .order(comments::id.asc())
+            .inner_join(users::table)
+            .select((comments::all_columns, USER_COLUMNS, has_replies))
+            .limit(remaining as i64 + 1)
+            .load::<(Comment, User, bool)>(connection)
+            .await?;
+
+        let with_replies = |level: &[(Comment, User, bool)]| -> Vec<i32> {
+            level.iter().filter(|row| row.2).map(|(comment, ..)| comment.id).collect()
+        };
+        if level.len() > remaining {
+            level.truncate(remaining);
+            let kept = level.iter().map(|(comment, ..)| comment.id).collect();
+            parents.extend(with_replies(&level));
+            unexplored = (parents, kept);
+            parents = Vec::new();
+        } else {
+            loaded += level.len();
+            parents = with_replies(&level);
+        }
+        for (comment, user, _) in level {
+            if let Some(parent_id) = comment.parent_id {
+                replies.entry(parent_id).or_default().push((comment, user));
+            }
+        }
+    }
+
+    let (frontier, seen) = unexplored;
+    let truncated: HashSet<i32> = if frontier.is_empty() {
+        HashSet::new()
+    } else {
+        comments::table
+            .filter(comments::parent_id.eq_any(&frontier))
+            .filter(comments::id.ne_all(&seen))
+            .filter(comments::status.eq_any(statuses))
+            .select(comments::parent_id.assume_not_null())
+            .distinct()
+            .load::<i32>(connection)
+            .await?
+            .into_iter()
+            .collect()
+    };
+
+    Ok(Page {
+        items: roots
+            .items
+            .into_iter()
+            .map(|(comment, user, ..)| comment_thread(comment, user, &mut replies, &truncated, 1, max_depth))
+            .collect(),
+        next: roots.next,
+    })
 }
 
-pub fn user_comments(connection: &SqliteConnection, user_id: i32) -> Result<Vec<(Comment, PostWithComment)>> {
-    comments::table
+fn comment_thread(
+    comment: Comment,
+    user: User,
+    pending: &mut HashMap<i32, Vec<(Comment, User)>>,
+    truncated: &HashSet<i32>,
+    depth: usize,
+    max_depth: usize,
+) -> CommentThread {
+    let children = pending.remove(&comment.id).unwrap_or_default();
+
+    let replies = if depth < max_depth {
+        children
+            .into_iter()
+            .map(|(comment, user)| comment_thread(comment, user, pending, truncated, depth + 1, max_depth))
+            .collect()
+    } else {
+        let mut descendants = Vec::new();
+        let mut stack = children;
+        while let Some((comment, user)) = stack.pop() {
+            stack.extend(pending.remove(&comment.id).unwrap_or_default());
+            let has_more_replies = truncated.contains(&comment.id);
+            descendants.push(CommentThread { comment, user, replies: Vec::new(), has_more_replies });
+        }
+        descendants.sort_by_key(|thread| thread.comment.id);
+        descendants
+    };
+
+    let has_more_replies = truncated.contains(&comment.id);
+    CommentThread { comment, user, replies, has_more_replies }
+}
+
+/// Comments of `user_id`, leaving out the ones on drafts and scheduled posts
+/// unless `viewer` wrote the post.
+pub async fn user_comments(connection: &mut DbConnection, user_id: i32, page: &PageRequest, viewer: Option<i32>)
+    -> Result<Page<(Comment, PostWithComment)>> {
+    let mut query = comments::table
         .filter(comments::user_id.eq(user_id))
+        .filter(comments::status.eq(CommentStatus::Approved))
+        .order(comments::id.asc())
         .inner_join(posts::table)
         .select((comments::all_columns, (posts::id, posts::title, posts::published)))
-        .load::<(Comment, PostWithComment)>(connection)
+        .limit(page.limit + 1)
+        .into_boxed();
+    query = match viewer {
+        Some(viewer) => query.filter(posts::published.eq(true).or(posts::user_id.eq(viewer))),
+        None => query.filter(posts::published.eq(true)),
+    };
+    if let Some(after) = page.after {
+        query = query.filter(comments::id.gt(after));
+    }
+
+    let comments = query.load::<(Comment, PostWithComment)>(connection).await?;
+    Ok(paginate(comments, page, |(comment, _)| comment.id))
+}
+
+pub async fn find_comment(connection: &mut DbConnection, comment_id: i32) -> Result<Comment> {
+    comments::table
+        .find(comment_id)
+        .select(comments::all_columns)
+        .first(connection)
+        .await
         .map_err(Into::into)
 }
+
+pub async fn set_comment_status(connection: &mut DbConnection, comment_id: i32, status: CommentStatus) -> Result<Comment> {
+    let now = Utc::now().naive_utc();
+
+    diesel::update(comments::table.find(comment_id))
+        .set((comments::status.eq(status), comments::updated_at.eq(now)))
+        .execute(connection)
+        .await?;
+
+    find_comment(connection, comment_id).await
+}
+
+/// Comments waiting for approval on the posts of `author_id`, oldest first,
+/// with their authors and posts.
+pub async fn pending_comments(connection: &mut DbConnection, author_id: i32, page: &PageRequest)
+    -> Result<Page<(Comment, User, PostWithComment)>> {
+    let mut query = comments::table
+        .inner_join(posts::table)
+        .inner_join(users::table.on(users::id.eq(comments::user_id)))
+        .filter(posts::user_id.eq(author_id))
+        .filter(comments::status.eq(CommentStatus::Pending))
+        .order(comments::id.asc())
+        .select((comments::all_columns, USER_COLUMNS, (posts::id, posts::title, posts::published)))
+        .limit(page.limit + 1)
+        .into_boxed();
+    if let Some(after) = page.after {
+        query = query.filter(comments::id.gt(after));
+    }
+
+    let comments = query.load::<(Comment, User, PostWithComment)>(connection).await?;
+    Ok(paginate(comments, page, |(comment, _, _)| comment.id))
+}
+
+/// Deletes every comment of a user. Replies from other users move up to the
+/// closest remaining ancestor so their threads stay reachable.
+pub async fn delete_user_comments(connection: &mut DbConnection, user_id: i32) -> Result<usize> {
+    transaction(connection, |connection| {
+        async move {
+            // Newest first: a reply is always newer than its parent, so it is moved
+            // before the comment it was moved onto gets its own replies moved up.
+            let purged = comments::table
+                .filter(comments::user_id.eq(user_id))
+                .order(comments::id.desc())
+                .select((comments::id, comments::parent_id))
+                .load::<(i32, Option<i32>)>(connection)
+                .await?;
+            for (id, parent_id) in purged {
+                diesel::update(comments::table.filter(comments::parent_id.eq(id)))
+                    .set(comments::parent_id.eq(parent_id))
+                    .execute(connection)
+                    .await?;
+            }
+
+            diesel::delete(comments::table.filter(comments::user_id.eq(user_id)))
+                .execute(connection)
+                .await
+                .map_err(Into::into)
+        }
+        .scope_boxed()
+    })
+    .await
+}
+
+/// Renders the html of rows stored before bodies were rendered on write.
+pub async fn render_missing_html(connection: &mut DbConnection) -> Result<()> {
+    transaction(connection, |connection| {
+        async move {
+            let posts = posts::table
+                .filter(posts::body_html.eq(""))
+                .filter(posts::body.ne(""))
+                .select((posts::id, posts::body))
+                .load::<(i32, String)>(connection)
+                .await?;
+            for (id, body) in posts {
+                diesel::update(posts::table.find(id))
+                    .set(posts::body_html.eq(markdown::render_post(body.as_str())))
+                    .execute(connection)
+                    .await?;
+            }
+
+            let comments = comments::table
+                .filter(comments::body_html.eq(""))
+                .filter(comments::body.ne(""))
+                .select((comments::id, comments::body))
+                .load::<(i32, String)>(connection)
+                .await?;
+            for (id, body) in comments {
+                diesel::update(comments::table.find(id))
+                    .set(comments::body_html.eq(markdown::render_comment(body.as_str())))
+                    .execute(connection)
+                    .await?;
+            }
+
+            Ok(())
+        }
+        .scope_boxed()
+    })
+    .await
+}
+
+// Export ///
+/// A user row as stored, password hash included.
+#[derive(Queryable, Insertable, Serialize, Deserialize, Debug)]
+#[diesel(table_name = users)]
+pub struct UserRecord {
+    pub id: i32,
+    pub username: String,
+    pub password_hash: String,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+/// Every row of the database, keeping ids so references between rows survive an import.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Dump {
+    pub users: Vec<UserRecord>,
+    pub posts: Vec<Post>,
+    pub tags: Vec<Tag>,
+    pub post_tags: Vec<PostTag>,
+    pub comments: Vec<Comment>,
+    #[serde(default)]
+    pub post_revisions: Vec<PostRevision>,
+}
+
+pub async fn export(connection: &mut DbConnection) -> Result<Dump> {
+    Ok(Dump {
+        users: users::table.order(users::id).load(connection).await?,
+        posts: posts::table.order(posts::id).load(connection).await?,
+        tags: tags::table.order(tags::id).load(connection).await?,
+        post_tags: post_tags::table.order((post_tags::post_id, post_tags::tag_id)).load(connection).await?,
+        comments: comments::table.order(comments::id).load(connection).await?,
+        post_revisions: post_revisions::table.order(post_revisions::id).load(connection).await?,
+    })
+}
+
+/// Inserts a dump, meant for an empty database: any id already taken fails
+/// the whole import with `RecordAlreadyExists`.
+pub async fn import(connection: &mut DbConnection, dump: &Dump) -> Result<()> {
+    transaction(connection, |connection| {
+        async move {
+            // One row at a time: batch inserts aren't available on SQLite through diesel-async.
+            for user in &dump.users {
+                diesel::insert_into(users::table).values(user).execute(connection).await?;
+            }
+            for post in &dump.posts {
+                diesel::insert_into(posts::table).values(post).execute(connection).await?;
+            }
+            for tag in &dump.tags {
+                diesel::insert_into(tags::table).values(tag).execute(connection).await?;
+            }
+            for post_tag in &dump.post_tags {
+                diesel::insert_into(post_tags::table).values(post_tag).execute(connection).await?;
+            }
+            for comment in &dump.comments {
+                diesel::insert_into(comments::table).values(comment).execute(connection).await?;
+            }
+            for revision in &dump.post_revisions {
+                diesel::insert_into(post_revisions::table).values(revision).execute(connection).await?;
+            }
+
+            // Rows were inserted with their ids, so move the sequences past them.
+            #[cfg(feature = "postgres")]
+            for table in &["users", "posts", "tags", "comments", "post_revisions"] {
+                diesel::sql_query(format!(
+                    "SELECT setval(pg_get_serial_sequence('{0}', 'id'), COALESCE(MAX(id), 0) + 1, false) FROM {0}",
+                    table
+                ))
+                .execute(connection)
+                .await?;
+            }
+
+            Ok(())
+        }
+        .scope_boxed()
+    })
+    .await
+}
+
+// Pagination ///
+pub struct PageRequest {
+    pub limit: i64,
+    pub after: Option<i32>,
+    pub sort: PostSort,
+}
+
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub next: Option<i32>,
+}
+
+/// Listings load one row past `limit` to know whether another page follows.
+fn paginate<T, F>(mut items: Vec<T>, page: &PageRequest, id: F) -> Page<T>
+where
+    F: Fn(&T) -> i32,
+{
+    let next = if items.len() as i64 > page.limit {
+        items.truncate(page.limit as usize);
+        items.last().map(id)
+    } else {
+        None
+    };
+
+    Page { items, next }
+}
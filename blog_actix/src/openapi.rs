@@ -0,0 +1,594 @@
+//! OpenAPI 3.1 description of every route, served at `/openapi.json`.
+//!
+//! The document is written by hand, so two tests keep it honest: the one at
+//! the bottom of this file scans the route definitions in `routes/*.rs` for
+//! routes without an `Operation`, and `routes::docs` validates a real response
+//! of every JSON operation against the schema documented for it.
+
+use serde_json::{json, Map, Value};
+
+enum Content {
+    Json(Value),
+    Html,
+    Xml(&'static str),
+}
+
+struct Operation {
+    method: &'static str,
+    path: &'static str,
+    summary: &'static str,
+    authenticated: bool,
+    /// Query parameters: name, schema type and description.
+    query: &'static [(&'static str, &'static str, &'static str)],
+    body: Option<&'static str>,
+    response: Content,
+    /// Sends an `ETag` and answers `304` to a matching `If-None-Match`.
+    conditional: bool,
+}
+
+const PAGE_QUERY: &[(&str, &str, &str)] = &[
+    ("limit", "integer", "Items per page, 1 to 100 (default 20)"),
+    ("cursor", "string", "`next_cursor` of the previous page"),
+];
+
+const POST_LIST_QUERY: &[(&str, &str, &str)] = &[
+    ("limit", "integer", "Items per page, 1 to 100 (default 20)"),
+    ("cursor", "string", "`next_cursor` of the previous page"),
+    ("sort", "string", "`id` (default), `created_at`, `updated_at` or `published_at`, newest first"),
+    ("tag", "string", "Only posts with this tag"),
+];
+
+const FEED_QUERY: &[(&str, &str, &str)] = &[("limit", "integer", "Number of entries, 1 to 100 (default 20)")];
+
+fn schema(name: &str) -> Value {
+    json!({ "$ref": format!("#/components/schemas/{}", name) })
+}
+
+fn array_of(items: Value) -> Value {
+    json!({ "type": "array", "items": items })
+}
+
+/// Fixed length array, for the handlers that return tuples.
+fn tuple_of(items: Vec<Value>) -> Value {
+    let length = items.len();
+    json!({ "type": "array", "prefixItems": items, "items": false, "minItems": length })
+}
+
+fn page_of(items: Value) -> Value {
+    json!({
+        "type": "object",
+        "required": ["items", "next_cursor"],
+        "properties": {
+            "items": array_of(items),
+            "next_cursor": { "type": ["string", "null"] },
+        },
+    })
+}
+
+fn operations() -> Vec<Operation> {
+    use Content::*;
+
+    let operation = |method, path, summary, response| Operation {
+        method,
+        path,
+        summary,
+        authenticated: false,
+        query: &[],
+        body: None,
+        response,
+        conditional: false,
+    };
+
+    vec![
+        Operation {
+            body: Some("UserInput"),
+            ..operation("post", "/api/users", "Create a user", Json(schema("User")))
+        },
+        operation("get", "/api/users/{id}", "Get a user", Json(schema("User"))),
+        operation("get", "/api/users/find/{name}", "Find a user by username", Json(schema("User"))),
+        Operation {
+            body: Some("SessionInput"),
+            ..operation("post", "/api/sessions", "Log in and get a token", Json(schema("Session")))
+        },
+        Operation {
+            query: POST_LIST_QUERY,
+            conditional: true,
+            ..operation("get", "/api/posts", "List published posts", Json(page_of(schema("PostListItem"))))
+        },
+        Operation {
+            authenticated: true,
+            body: Some("PostInput"),
+            ..operation("post", "/api/users/{id}/posts", "Create a post as this user", Json(schema("Post")))
+        },
+        Operation {
+            query: POST_LIST_QUERY,
+            conditional: true,
+            ..operation("get", "/api/users/{id}/posts", "List the posts of a user, drafts only for their author", Json(page_of(schema("UserPostListItem"))))
+        },
+        operation("get", "/api/posts/{id}", "Get a post, drafts only for their author", Json(schema("Post"))),
+        Operation {
+            authenticated: true,
+            body: Some("PostUpdateInput"),
+            ..operation("patch", "/api/posts/{id}", "Update a post", Json(schema("Post")))
+        },
+        Operation {
+            authenticated: true,
+            ..operation("delete", "/api/posts/{id}", "Delete a post with its comments", Json(schema("Post")))
+        },
+        Operation {
+            authenticated: true,
+            ..operation("post", "/api/posts/{id}/publish", "Publish a post", Json(schema("Post")))
+        },
+        Operation {
+            authenticated: true,
+            query: PAGE_QUERY,
+            ..operation("get", "/api/posts/{id}/revisions", "List the revisions of one of your posts, newest first", Json(page_of(schema("PostRevision"))))
+        },
+        Operation {
+            authenticated: true,
+            query: &[("to", "integer", "Revision to compare with (default the newest)")],
+            ..operation("get", "/api/posts/{id}/revisions/{revision_id}/diff", "Diff a revision with another one", Json(schema("RevisionDiff")))
+        },
+        Operation {
+            authenticated: true,
+            ..operation("post", "/api/posts/{id}/revisions/{revision_id}/restore", "Restore the title and body of a revision", Json(schema("Post")))
+        },
+        Operation {
+            authenticated: true,
+            body: Some("CommentInput"),
+            ..operation("post", "/api/posts/{id}/comments", "Comment on a post", Json(schema("Comment")))
+        },
+        Operation {
+            query: &[
+                ("limit", "integer", "Top level comments per page, 1 to 100 (default 20)"),
+                ("cursor", "string", "`next_cursor` of the previous page"),
+                ("depth", "integer", "Levels of nested replies, 1 to 20 (default 5)"),
+            ],
+            ..operation("get", "/api/posts/{id}/comments", "List the comments of a post", Json(page_of(schema("CommentThread"))))
+        },
+        Operation {
+            query: PAGE_QUERY,
+            ..operation("get", "/api/users/{id}/comments", "List the comments of a user", Json(page_of(schema("UserComment"))))
+        },
+        Operation {
+            authenticated: true,
+            query: PAGE_QUERY,
+            ..operation("get", "/api/comments/pending", "List the comments waiting for approval on your posts", Json(page_of(schema("PendingComment"))))
+        },
+        Operation {
+            authenticated: true,
+            ..operation("post", "/api/comments/{id}/approve", "Approve a comment on one of your posts", Json(schema("Comment")))
+        },
+        Operation {
+            authenticated: true,
+            ..operation("post", "/api/comments/{id}/reject", "Reject a comment on one of your posts", Json(schema("Comment")))
+        },
+        operation("get", "/api/tags", "List tags with their number of published posts", Json(array_of(schema("TagCount")))),
+        operation("get", "/api/posts/{id}/tags", "List the tags of a post", Json(array_of(schema("Tag")))),
+        Operation {
+            authenticated: true,
+            body: Some("TagInput"),
+            ..operation("post", "/api/posts/{id}/tags", "Tag a post", Json(array_of(schema("Tag"))))
+        },
+        Operation {
+            authenticated: true,
+            ..operation("delete", "/api/posts/{id}/tags/{name}", "Remove a tag from a post", Json(array_of(schema("Tag"))))
+        },
+        Operation {
+            query: &[
+                ("q", "string", "Terms to search, all of them must match"),
+                ("limit", "integer", "Number of results, 1 to 100 (default 20)"),
+            ],
+            ..operation("get", "/api/search", "Search published posts and their comments", Json(array_of(schema("SearchResult"))))
+        },
+        Operation {
+            query: FEED_QUERY,
+            conditional: true,
+            ..operation("get", "/feed.rss", "RSS feed of published posts", Xml("application/rss+xml"))
+        },
+        Operation {
+            query: FEED_QUERY,
+            conditional: true,
+            ..operation("get", "/feed.atom", "Atom feed of published posts", Xml("application/atom+xml"))
+        },
+        Operation {
+            query: FEED_QUERY,
+            conditional: true,
+            ..operation("get", "/users/{id}/feed.atom", "Atom feed of a user's published posts", Xml("application/atom+xml"))
+        },
+        operation("get", "/healthz", "Liveness check", Json(schema("Health"))),
+        operation("get", "/readyz", "Readiness check, 503 without a database connection", Json(schema("Health"))),
+        Operation {
+            query: PAGE_QUERY,
+            ..operation("get", "/", "Web page listing published posts", Html)
+        },
+        operation("get", "/posts/{id}", "Web page of a published post", Html),
+        Operation {
+            query: PAGE_QUERY,
+            ..operation("get", "/users/{id}", "Web page of a user's published posts", Html)
+        },
+        operation("get", "/openapi.json", "This document", Json(json!({ "type": "object" }))),
+        operation("get", "/docs", "Web page browsing this document", Html),
+    ]
+}
+
+fn parameters(operation: &Operation) -> Vec<Value> {
+    let path = operation
+        .path
+        .split('/')
+        .filter(|segment| segment.starts_with('{') && segment.ends_with('}'))
+        .map(|segment| {
+            let name = &segment[1..segment.len() - 1];
+            let kind = if name == "id" || name.ends_with("_id") { "integer" } else { "string" };
+            json!({ "name": name, "in": "path", "required": true, "schema": { "type": kind } })
+        });
+    let query = operation.query.iter().map(|(name, kind, description)| {
+        json!({ "name": name, "in": "query", "description": description, "schema": { "type": kind } })
+    });
+
+    path.chain(query).collect()
+}
+
+fn operation_object(operation: &Operation) -> Value {
+    let content = match &operation.response {
+        Content::Json(body) => json!({ "application/json": { "schema": body } }),
+        Content::Html => json!({ "text/html": { "schema": { "type": "string" } } }),
+        Content::Xml(media_type) => json!({ *media_type: { "schema": { "type": "string" } } }),
+    };
+
+    let mut object = json!({
+        "summary": operation.summary,
+        "parameters": parameters(operation),
+        "responses": {
+            "200": { "description": "Success", "content": content },
+            "default": {
+                "description": "Error",
+                "content": { "application/json": { "schema": schema("ErrorResponse") } },
+            },
+        },
+    });
+    if let Some(body) = operation.body {
+        object["requestBody"] = json!({
+            "required": true,
+            "content": { "application/json": { "schema": schema(body) } },
+        });
+    }
+    if operation.conditional {
+        object["responses"]["304"] = json!({ "description": "Not modified since the `ETag` sent in `If-None-Match`" });
+    }
+    if operation.authenticated {
+        object["security"] = json!([{ "bearer": [] }]);
+    }
+
+    object
+}
+
+fn schemas() -> Value {
+    let timestamp = json!({ "type": "string", "description": "UTC date and time, without offset" });
+    let nullable_timestamp = json!({ "type": ["string", "null"], "description": "UTC date and time, without offset" });
+    let comment_with_user = tuple_of(vec![schema("Comment"), schema("User")]);
+
+    json!({
+        "UserInput": {
+            "type": "object",
+            "required": ["username", "password"],
+            "properties": {
+                "username": { "type": "string", "minLength": 3, "maxLength": 32, "description": "Letters, digits, `_`, `-` and `.`" },
+                "password": { "type": "string", "minLength": 8, "maxLength": 128 },
+            },
+        },
+        "SessionInput": {
+            "type": "object",
+            "required": ["username", "password"],
+            "properties": {
+                "username": { "type": "string" },
+                "password": { "type": "string" },
+            },
+        },
+        "PostInput": {
+            "type": "object",
+            "required": ["title", "body"],
+            "properties": {
+                "title": { "type": "string", "minLength": 1, "maxLength": 200 },
+                "body": { "type": "string", "minLength": 1, "maxLength": 50000, "description": "Markdown" },
+            },
+        },
+        "PostUpdateInput": {
+            "type": "object",
+            "properties": {
+                "title": { "type": "string", "minLength": 1, "maxLength": 200 },
+                "body": { "type": "string", "minLength": 1, "maxLength": 50000, "description": "Markdown" },
+                "published": { "type": "boolean" },
+                "comments_closed": { "type": "boolean" },
+                "comments_moderated": { "type": "boolean" },
+                "publish_at": {
+                    "type": ["string", "null"],
+                    "description": "UTC date and time, without offset, at which to publish the post, which must be unpublished; null cancels the schedule",
+                },
+            },
+        },
+        "CommentInput": {
+            "type": "object",
+            "required": ["body"],
+            "properties": {
+                "body": { "type": "string", "minLength": 1, "maxLength": 5000, "description": "Markdown" },
+                "parent_id": { "type": ["integer", "null"], "description": "Comment of the same post to reply to" },
+            },
+        },
+        "TagInput": {
+            "type": "object",
+            "required": ["name"],
+            "properties": { "name": { "type": "string", "minLength": 1, "maxLength": 50 } },
+        },
+        "User": {
+            "type": "object",
+            "required": ["id", "username", "created_at", "updated_at"],
+            "properties": {
+                "id": { "type": "integer" },
+                "username": { "type": "string" },
+                "created_at": timestamp,
+                "updated_at": timestamp,
+            },
+        },
+        "Session": {
+            "type": "object",
+            "required": ["token", "user"],
+            "properties": {
+                "token": { "type": "string", "description": "Send as `Authorization: Bearer <token>`" },
+                "user": schema("User"),
+            },
+        },
+        "Post": {
+            "type": "object",
+            "required": [
+                "id", "user_id", "title", "body", "published", "body_html", "created_at", "updated_at", "published_at",
+                "comments_closed", "comments_moderated", "publish_at",
+            ],
+            "properties": {
+                "id": { "type": "integer" },
+                "user_id": { "type": "integer" },
+                "title": { "type": "string" },
+                "body": { "type": "string" },
+                "published": { "type": "boolean" },
+                "body_html": { "type": "string" },
+                "created_at": timestamp,
+                "updated_at": timestamp,
+                "published_at": nullable_timestamp,
+                "comments_closed": { "type": "boolean", "description": "No new comments, from anyone" },
+                "comments_moderated": { "type": "boolean", "description": "Comments of other users wait for the author's approval" },
+                "publish_at": nullable_timestamp,
+            },
+        },
+        "PostRevision": {
+            "type": "object",
+            "required": ["id", "post_id", "title", "body", "created_at"],
+            "properties": {
+                "id": { "type": "integer" },
+                "post_id": { "type": "integer" },
+                "title": { "type": "string" },
+                "body": { "type": "string" },
+                "created_at": timestamp,
+            },
+        },
+        "RevisionDiff": {
+            "type": "object",
+            "required": ["from", "to", "title", "body"],
+            "properties": {
+                "from": { "type": "integer" },
+                "to": { "type": "integer" },
+                "title": { "type": "string", "description": "Unified diff, empty when unchanged" },
+                "body": { "type": "string", "description": "Unified diff, empty when unchanged" },
+            },
+        },
+        "Comment": {
+            "type": "object",
+            "required": ["id", "user_id", "post_id", "body", "parent_id", "body_html", "created_at", "updated_at", "status"],
+            "properties": {
+                "id": { "type": "integer" },
+                "user_id": { "type": "integer" },
+                "post_id": { "type": "integer" },
+                "body": { "type": "string" },
+                "parent_id": { "type": ["integer", "null"] },
+                "body_html": { "type": "string" },
+                "created_at": timestamp,
+                "updated_at": timestamp,
+                "status": {
+                    "type": "string",
+                    "enum": ["pending", "approved", "rejected"],
+                    "description": "Only approved comments are listed, pending ones also to the author of the post",
+                },
+            },
+        },
+        "PostWithComment": {
+            "type": "object",
+            "required": ["id", "title", "published"],
+            "properties": {
+                "id": { "type": "integer" },
+                "title": { "type": "string" },
+                "published": { "type": "boolean" },
+            },
+        },
+        "CommentThread": {
+            "type": "object",
+            "required": ["comment", "user", "replies", "has_more_replies"],
+            "properties": {
+                "comment": schema("Comment"),
+                "user": schema("User"),
+                "replies": array_of(schema("CommentThread")),
+                "has_more_replies": { "type": "boolean" },
+            },
+        },
+        "Tag": {
+            "type": "object",
+            "required": ["id", "name"],
+            "properties": {
+                "id": { "type": "integer" },
+                "name": { "type": "string" },
+            },
+        },
+        "TagCount": {
+            "type": "object",
+            "required": ["id", "name", "post_count"],
+            "properties": {
+                "id": { "type": "integer" },
+                "name": { "type": "string" },
+                "post_count": { "type": "integer" },
+            },
+        },
+        "PostListItem": {
+            "type": "array",
+            "description": "`[[Post, User], [[Comment, User], ...], [Tag, ...]]`: a post with its author, comments and tags",
+            "prefixItems": [tuple_of(vec![schema("Post"), schema("User")]), array_of(comment_with_user.clone()), array_of(schema("Tag"))],
+            "items": false,
+            "minItems": 3,
+        },
+        "UserPostListItem": {
+            "type": "array",
+            "description": "`[Post, [[Comment, User], ...], [Tag, ...]]`: a post with its comments and tags",
+            "prefixItems": [schema("Post"), array_of(comment_with_user), array_of(schema("Tag"))],
+            "items": false,
+            "minItems": 3,
+        },
+        "PendingComment": {
+            "type": "array",
+            "description": "`[Comment, User, PostWithComment]`: a comment with its author and post",
+            "prefixItems": [schema("Comment"), schema("User"), schema("PostWithComment")],
+            "items": false,
+            "minItems": 3,
+        },
+        "UserComment": {
+            "type": "array",
+            "description": "`[Comment, PostWithComment]`: a comment with the post it was made on",
+            "prefixItems": [schema("Comment"), schema("PostWithComment")],
+            "items": false,
+            "minItems": 2,
+        },
+        "SearchResult": {
+            "type": "object",
+            "required": ["post", "user", "matched", "snippet", "rank"],
+            "properties": {
+                "post": schema("Post"),
+                "user": schema("User"),
+                "matched": { "type": "string", "enum": ["post", "comment"] },
+                "snippet": { "type": "string", "description": "Escaped text with the matched terms wrapped in `<mark>`" },
+                "rank": { "type": "number", "description": "Lower is more relevant, only comparable between hits of the same `matched`" },
+            },
+        },
+        "Health": {
+            "type": "object",
+            "required": ["status", "pool"],
+            "properties": {
+                "status": { "type": "string", "enum": ["ok", "unavailable"] },
+                "pool": {
+                    "type": "object",
+                    "properties": {
+                        "connections": { "type": "integer" },
+                        "idle_connections": { "type": "integer" },
+                        "max_size": { "type": "integer" },
+                    },
+                },
+            },
+        },
+        "ErrorResponse": {
+            "type": "object",
+            "required": ["err"],
+            "properties": {
+                "err": { "type": "string" },
+                "fields": {
+                    "type": "object",
+                    "description": "Failed validation rules by field, on `422` responses",
+                    "additionalProperties": array_of(json!({ "type": "string" })),
+                },
+            },
+        },
+    })
+}
+
+pub fn document() -> Value {
+    let mut paths = Map::new();
+    for operation in operations() {
+        let item = paths.entry(operation.path).or_insert_with(|| json!({}));
+        item[operation.method] = operation_object(&operation);
+    }
+
+    json!({
+        "openapi": "3.1.0",
+        "info": { "title": "Blog", "version": env!("CARGO_PKG_VERSION") },
+        "paths": paths,
+        "components": {
+            "schemas": schemas(),
+            "securitySchemes": { "bearer": { "type": "http", "scheme": "bearer" } },
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+    use std::fs;
+
+    const METHODS: [&str; 5] = ["get", "post", "put", "patch", "delete"];
+
+    /// `(method, path)` of every `web::resource(...).route(web::<method>()...)`
+    /// in a routes module, paths prefixed with `prefix`.
+    fn declared_routes(source: &str, prefix: &str) -> Vec<(String, String)> {
+        let mut routes = Vec::new();
+        let mut path = None;
+        for (i, _) in source.match_indices("web::") {
+            let rest = &source[i + "web::".len()..];
+            if rest.starts_with("resource(\"") {
+                let start = "resource(\"".len();
+                let end = start + rest[start..].find('"').unwrap();
+                path = Some(format!("{}{}", prefix, &rest[start..end]));
+            } else if let Some(method) = METHODS.iter().find(|method| rest.starts_with(&format!("{}()", method))) {
+                let path = path.clone().expect("route before any resource");
+                routes.push((method.to_string(), path));
+            }
+        }
+
+        routes
+    }
+
+    #[test]
+    fn every_route_is_documented() {
+        let lib = fs::read_to_string("src/lib.rs").unwrap();
+        let api_start = lib.find("web::scope(\"/api\")").unwrap();
+        let api_scope = &lib[api_start..api_start + lib[api_start..].find(");").unwrap()];
+
+        let mut declared = BTreeSet::new();
+        for entry in fs::read_dir("src/routes").unwrap() {
+            let path = entry.unwrap().path();
+            let module = path.file_stem().unwrap().to_str().unwrap().to_string();
+            let prefix = if api_scope.contains(&format!("routes::{}::configure", module)) {
+                "/api"
+            } else {
+                ""
+            };
+            declared.extend(declared_routes(&fs::read_to_string(&path).unwrap(), prefix));
+        }
+
+        let documented: BTreeSet<(String, String)> = operations()
+            .into_iter()
+            .map(|operation| (operation.method.to_string(), operation.path.to_string()))
+            .collect();
+
+        let undocumented: Vec<_> = declared.difference(&documented).collect();
+        assert!(undocumented.is_empty(), "routes missing from the OpenAPI document: {:?}", undocumented);
+        let unknown: Vec<_> = documented.difference(&declared).collect();
+        assert!(unknown.is_empty(), "documented routes that don't exist: {:?}", unknown);
+    }
+
+    #[test]
+    fn every_schema_reference_is_defined() {
+        let document = document();
+        let schemas = document["components"]["schemas"].as_object().unwrap();
+        let text = document.to_string();
+
+        for (i, _) in text.match_indices("#/components/schemas/") {
+            let rest = &text[i + "#/components/schemas/".len()..];
+            let name = &rest[..rest.find('"').unwrap()];
+            assert!(schemas.contains_key(name), "undefined schema {}", name);
+        }
+    }
+}
@@ -0,0 +1,254 @@
+//! Per client rate limits, as token buckets shared by every worker.
+//!
+//! `RateLimit` wraps a route and takes a token from the buckets of the client
+//! IP address and, with a valid bearer token, of the user. A request finding
+//! any of them empty is refused with `429 Too Many Requests` and `Retry-After`.
+//!
+//! The buckets are capped at `MAX_BUCKETS`. `sweep` drops the ones that have
+//! refilled and then the least recently used, and runs on a timer rather than
+//! on requests; until it makes room, clients without a bucket are refused.
+
+use crate::auth;
+use crate::config::RateLimitConfig;
+use crate::errors::AppError;
+use actix_web::body::EitherBody;
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::{web, Error};
+use futures::future::{ready, Either, MapOk, Ready, TryFutureExt};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+/// Most buckets kept at once, no new client gets one past it.
+const MAX_BUCKETS: usize = 10_000;
+/// Buckets left by a sweep, so that new clients find room until the next one.
+const SWEEP_TARGET: usize = MAX_BUCKETS * 3 / 4;
+/// Time between two sweeps, also the `Retry-After` of clients finding no room.
+pub const SWEEP_PERIOD: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Client {
+    Ip(IpAddr),
+    User(i32),
+}
+
+struct Bucket {
+    limit: RateLimitConfig,
+    tokens: f64,
+    updated: Instant,
+    /// Last request of the client, which `updated` doesn't track once swept.
+    used: Instant,
+}
+
+impl Bucket {
+    fn new(limit: RateLimitConfig, now: Instant) -> Self {
+        Bucket {
+            limit,
+            tokens: f64::from(limit.burst),
+            updated: now,
+            used: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        let refilled = self.tokens + elapsed * f64::from(self.limit.per_minute) / 60.0;
+        self.tokens = refilled.min(f64::from(self.limit.burst));
+        self.updated = now;
+    }
+
+    fn is_full(&self) -> bool {
+        self.tokens >= f64::from(self.limit.burst)
+    }
+
+    /// Seconds until the bucket has a token.
+    fn wait(&self) -> f64 {
+        (1.0 - self.tokens).max(0.0) * 60.0 / f64::from(self.limit.per_minute)
+    }
+}
+
+/// The buckets of every limited route, keyed by route name and client.
+#[derive(Clone, Default)]
+pub struct RateLimiter {
+    buckets: Arc<Mutex<HashMap<(&'static str, Client), Bucket>>>,
+}
+
+impl RateLimiter {
+    fn lock(&self) -> MutexGuard<'_, HashMap<(&'static str, Client), Bucket>> {
+        // A bucket is consistent after any panic, nothing to recover.
+        self.buckets.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Takes a token from the bucket of each client, or none at all if one of
+    /// them is empty, then failing with the seconds until it has a token again.
+    fn acquire(&self, route: &'static str, limit: RateLimitConfig, clients: &[Client]) -> Result<(), u64> {
+        let now = Instant::now();
+        let mut buckets = self.lock();
+
+        let missing = clients.iter().filter(|client| !buckets.contains_key(&(route, **client))).count();
+        if buckets.len() + missing > MAX_BUCKETS {
+            return Err(SWEEP_PERIOD.as_secs());
+        }
+
+        let mut wait: f64 = 0.0;
+        for client in clients {
+            let bucket = buckets.entry((route, *client)).or_insert_with(|| Bucket::new(limit, now));
+            bucket.refill(now);
+            bucket.used = now;
+            wait = wait.max(bucket.wait());
+        }
+        if wait > 0.0 {
+            return Err(wait.ceil() as u64);
+        }
+
+        for client in clients {
+            if let Some(bucket) = buckets.get_mut(&(route, *client)) {
+                bucket.tokens -= 1.0;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Drops the buckets that have refilled, which are no different from new
+    /// ones, then the least recently used ones down to `SWEEP_TARGET`.
+    fn sweep(&self, now: Instant) {
+        let mut buckets = self.lock();
+        buckets.retain(|_, bucket| {
+            bucket.refill(now);
+            !bucket.is_full()
+        });
+
+        if buckets.len() > SWEEP_TARGET {
+            let mut used: Vec<_> = buckets.iter().map(|(key, bucket)| (bucket.used, *key)).collect();
+            used.sort_unstable_by_key(|(used, _)| *used);
+            for (_, key) in &used[..buckets.len() - SWEEP_TARGET] {
+                buckets.remove(key);
+            }
+        }
+    }
+}
+
+/// Sweeps the buckets every `period`, for as long as the server runs.
+pub async fn sweep_buckets(limiter: RateLimiter, period: Duration) {
+    let mut interval = actix_web::rt::time::interval(period);
+    loop {
+        interval.tick().await;
+        limiter.sweep(Instant::now());
+    }
+}
+
+/// Middleware limiting the requests to a route. Requests go through untouched
+/// when the limit is off or the app has no `RateLimiter`.
+pub struct RateLimit {
+    route: &'static str,
+    limit: RateLimitConfig,
+}
+
+impl RateLimit {
+    pub fn new(route: &'static str, limit: RateLimitConfig) -> Self {
+        RateLimit { route, limit }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RateLimit
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = RateLimitMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RateLimitMiddleware {
+            service,
+            route: self.route,
+            limit: self.limit,
+        }))
+    }
+}
+
+pub struct RateLimitMiddleware<S> {
+    service: S,
+    route: &'static str,
+    limit: RateLimitConfig,
+}
+
+impl<S, B> Service<ServiceRequest> for RateLimitMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    #[allow(clippy::type_complexity)]
+    type Future = Either<MapOk<S::Future, fn(ServiceResponse<B>) -> Self::Response>, Ready<Result<Self::Response, Self::Error>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        if self.limit.burst > 0 {
+            if let Some(limiter) = req.app_data::<web::Data<RateLimiter>>() {
+                let mut clients = Vec::new();
+                if let Some(addr) = req.peer_addr() {
+                    clients.push(Client::Ip(addr.ip()));
+                }
+                if let Some(user_id) = auth::token_user_id(req.request()) {
+                    clients.push(Client::User(user_id));
+                }
+
+                if let Err(retry_after) = limiter.acquire(self.route, self.limit, &clients) {
+                    // A response rather than an error, so that the middleware
+                    // outside this one sees the 429 like any other response.
+                    let response = req.error_response(AppError::RateLimited(retry_after));
+                    return Either::Right(ready(Ok(response.map_into_right_body())));
+                }
+            }
+        }
+
+        Either::Left(self.service.call(req).map_ok(ServiceResponse::map_into_left_body))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    const LIMIT: RateLimitConfig = RateLimitConfig { burst: 2, per_minute: 1 };
+
+    fn client(n: usize) -> Client {
+        Client::Ip(IpAddr::V4(Ipv4Addr::from(n as u32)))
+    }
+
+    #[test]
+    fn new_clients_wait_for_a_sweep_when_full() {
+        let limiter = RateLimiter::default();
+        for n in 0..MAX_BUCKETS {
+            assert!(limiter.acquire("test", LIMIT, &[client(n)]).is_ok());
+        }
+
+        assert_eq!(limiter.acquire("test", LIMIT, &[client(MAX_BUCKETS)]), Err(SWEEP_PERIOD.as_secs()));
+        assert!(limiter.acquire("test", LIMIT, &[client(0)]).is_ok());
+
+        limiter.sweep(Instant::now());
+        assert_eq!(limiter.lock().len(), SWEEP_TARGET);
+        assert!(limiter.lock().contains_key(&("test", client(0))));
+        assert!(limiter.acquire("test", LIMIT, &[client(MAX_BUCKETS)]).is_ok());
+    }
+
+    #[test]
+    fn sweep_drops_refilled_buckets() {
+        let limiter = RateLimiter::default();
+        limiter.acquire("test", LIMIT, &[client(0)]).unwrap();
+
+        limiter.sweep(Instant::now() + Duration::from_secs(60));
+        assert!(limiter.lock().is_empty());
+    }
+}
@@ -1,10 +1,88 @@
+use crate::config::LimitsConfig;
 use crate::errors::AppError;
-use actix_web::HttpResponse;
+use crate::models;
+use actix_web::web;
 
 pub(super) mod comments;
+pub(super) mod docs;
+pub(super) mod feeds;
+pub(super) mod health;
+pub(super) mod pages;
 pub(super) mod posts;
+pub(super) mod revisions;
+pub(super) mod search;
+pub(super) mod sessions;
+pub(super) mod tags;
 pub(super) mod users;
 
-fn convert<T, E>(res: Result<T, E>) -> Result<HttpResponse, AppError> where T: serde::Serialize, AppError: From<E>, {
-    res.map(|d| HttpResponse::Ok().json(d)).map_err(Into::into)
+const DEFAULT_PAGE_LIMIT: i64 = 20;
+const MAX_PAGE_LIMIT: i64 = 100;
+
+/// Json extractor settings for every resource accepting a request body.
+fn json_config(limits: &LimitsConfig) -> web::JsonConfig {
+    web::JsonConfig::default()
+        .limit(limits.json_body)
+        .error_handler(|err, _req| AppError::from(err).into())
+}
+
+#[derive(Debug, Deserialize)]
+struct PageParams {
+    limit: Option<i64>,
+    cursor: Option<String>,
+    sort: Option<models::PostSort>,
+}
+
+impl PageParams {
+    fn to_request(&self) -> Result<models::PageRequest, AppError> {
+        let limit = self.limit.unwrap_or(DEFAULT_PAGE_LIMIT);
+        if !(1..=MAX_PAGE_LIMIT).contains(&limit) {
+            return Err(AppError::InvalidPage);
+        }
+
+        let after = match &self.cursor {
+            Some(cursor) => Some(decode_cursor(cursor)?),
+            None => None,
+        };
+
+        Ok(models::PageRequest {
+            limit,
+            after,
+            sort: self.sort.unwrap_or_default(),
+        })
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct PageResponse<T> {
+    items: Vec<T>,
+    next_cursor: Option<String>,
+}
+
+impl<T> From<models::Page<T>> for PageResponse<T> {
+    fn from(page: models::Page<T>) -> Self {
+        PageResponse {
+            items: page.items,
+            next_cursor: page.next.map(encode_cursor),
+        }
+    }
+}
+
+/// Whether an `If-None-Match` header value lists `etag`, or is `*`.
+fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    if_none_match.split(',').any(|tag| {
+        let tag = tag.trim();
+        tag == "*" || tag == etag
+    })
+}
+
+fn encode_cursor(id: i32) -> String {
+    base64::encode_config(id.to_string(), base64::URL_SAFE_NO_PAD)
+}
+
+fn decode_cursor(cursor: &str) -> Result<i32, AppError> {
+    base64::decode_config(cursor, base64::URL_SAFE_NO_PAD)
+        .ok()
+        .and_then(|bytes| String::from_utf8(bytes).ok())
+        .and_then(|id| id.parse().ok())
+        .ok_or(AppError::InvalidPage)
 }
@@ -1,52 +1,417 @@
+use crate::auth::{self, AuthUser};
+use crate::cache::ListingCache;
+use crate::config::LimitsConfig;
 use crate::errors::AppError;
-use crate::routes::convert;
+use crate::rate_limit::RateLimit;
+use crate::routes::{json_config, PageParams, PageResponse};
+use crate::validation::not_blank;
 use crate::{models, Pool};
-use actix_web::{web, HttpResponse};
-use diesel::prelude::*;
-use futures::Future;
+use actix_web::{web, HttpRequest, HttpResponse};
+use validator::Validate;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Validate)]
 struct CommentInput {
-    user_id: i32,
+    #[validate(length(min = 1, max = 5000), custom = "not_blank")]
     body: String,
+    parent_id: Option<i32>,
 }
 
-fn add_comment(post_id: web::Path<i32>, input: web::Json<CommentInput>, pool: web::Data<Pool>)
-    -> impl Future<Item = HttpResponse, Error = AppError> {
-        web::block(move || {
-            let connection: &SqliteConnection = &pool.get().unwrap();
-            let comment = input.into_inner();
-            models::create_comment(connection, comment.user_id, post_id.into_inner(), comment.body.as_str())
-        })
-        .then(convert)
+pub(super) const DEFAULT_THREAD_DEPTH: usize = 5;
+const MAX_THREAD_DEPTH: usize = 20;
+
+#[derive(Debug, Deserialize)]
+struct ThreadParams {
+    depth: Option<usize>,
+}
+
+async fn add_comment(post_id: web::Path<i32>, input: web::Json<CommentInput>, auth: AuthUser, pool: web::Data<Pool>, cache: web::Data<ListingCache>) -> Result<HttpResponse, AppError> {
+    let mut conn = pool.get().await?;
+    let comment = input.into_inner();
+    comment.validate()?;
+    let comment = models::create_comment(&mut conn, &auth.0, post_id.into_inner(), comment.parent_id, comment.body.as_str()).await?;
+    cache.invalidate();
+
+    Ok(HttpResponse::Ok().json(comment))
+}
+
+async fn post_comments(req: HttpRequest, post_id: web::Path<i32>, params: web::Query<PageParams>, thread: web::Query<ThreadParams>, pool: web::Data<Pool>) -> Result<HttpResponse, AppError> {
+    let mut conn = pool.get().await?;
+    let page = params.to_request()?;
+    let depth = thread.depth.unwrap_or(DEFAULT_THREAD_DEPTH).clamp(1, MAX_THREAD_DEPTH);
+    let viewer = auth::token_user_id(&req);
+    let page = models::post_comments(&mut conn, post_id.into_inner(), &page, depth, viewer).await.map(PageResponse::from)?;
+
+    Ok(HttpResponse::Ok().json(page))
 }
 
-fn post_comments(post_id: web::Path<i32>, pool: web::Data<Pool>)
-    -> impl Future<Item = HttpResponse, Error = AppError> {
-        web::block(move || {
-            let connection: &SqliteConnection = &pool.get().unwrap();
-            models::post_comments(connection, post_id.into_inner())
-        })
-        .then(convert)
+async fn user_comments(req: HttpRequest, user_id: web::Path<i32>, params: web::Query<PageParams>, pool: web::Data<Pool>) -> Result<HttpResponse, AppError> {
+    let mut conn = pool.get().await?;
+    let page = params.to_request()?;
+    let viewer = auth::token_user_id(&req);
+    let page = models::user_comments(&mut conn, user_id.into_inner(), &page, viewer).await.map(PageResponse::from)?;
+
+    Ok(HttpResponse::Ok().json(page))
 }
 
-fn user_comments(user_id: web::Path<i32>, pool: web::Data<Pool>)
-    -> impl Future<Item = HttpResponse, Error = AppError> {
-        web::block(move || {
-            let connection: &SqliteConnection = &pool.get().unwrap();
-            models::user_comments(connection, user_id.into_inner())
-        })
-        .then(convert)
+/// Comments waiting for the approval of the authenticated user, on their posts.
+async fn pending_comments(params: web::Query<PageParams>, auth: AuthUser, pool: web::Data<Pool>) -> Result<HttpResponse, AppError> {
+    let mut conn = pool.get().await?;
+    let page = params.to_request()?;
+    let page = models::pending_comments(&mut conn, auth.0.id, &page).await.map(PageResponse::from)?;
+
+    Ok(HttpResponse::Ok().json(page))
 }
 
-pub fn configure(cfg: &mut web::ServiceConfig) {
+/// Only the author of the post can moderate its comments.
+async fn moderate(comment_id: i32, status: models::CommentStatus, auth: AuthUser, pool: web::Data<Pool>, cache: web::Data<ListingCache>) -> Result<HttpResponse, AppError> {
+    let mut conn = pool.get().await?;
+    let comment = models::find_comment(&mut conn, comment_id).await?;
+    auth.authored_post(&mut conn, comment.post_id).await?;
+
+    let comment = models::set_comment_status(&mut conn, comment.id, status).await?;
+    cache.invalidate();
+
+    Ok(HttpResponse::Ok().json(comment))
+}
+
+async fn approve_comment(comment_id: web::Path<i32>, auth: AuthUser, pool: web::Data<Pool>, cache: web::Data<ListingCache>) -> Result<HttpResponse, AppError> {
+    moderate(comment_id.into_inner(), models::CommentStatus::Approved, auth, pool, cache).await
+}
+
+async fn reject_comment(comment_id: web::Path<i32>, auth: AuthUser, pool: web::Data<Pool>, cache: web::Data<ListingCache>) -> Result<HttpResponse, AppError> {
+    moderate(comment_id.into_inner(), models::CommentStatus::Rejected, auth, pool, cache).await
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig, limits: &LimitsConfig) {
     cfg.service(
             web::resource("/users/{id}/comments")
-                .route(web::get().to_async(user_comments))
+                .route(web::get().to(user_comments))
         )
+        .service(web::resource("/comments/pending").route(web::get().to(pending_comments)))
+        .service(web::resource("/comments/{id}/approve").route(web::post().to(approve_comment)))
+        .service(web::resource("/comments/{id}/reject").route(web::post().to(reject_comment)))
         .service(
             web::resource("/posts/{id}/comments")
-                .route(web::post().to_async(add_comment))
-                .route(web::get().to_async(post_comments)),
+                .app_data(json_config(limits))
+                .route(web::post().to(add_comment).wrap(RateLimit::new("create_comment", limits.create_comment)))
+                .route(web::get().to(post_comments)),
         );
-}
\ No newline at end of file
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use crate::config::LimitsConfig;
+    use crate::db::DbConnection;
+    use crate::models::{self, MAX_REPLY_LEVELS, MAX_THREAD_REPLIES};
+    use crate::test_utils::{create_post, create_user, send, test_pool};
+    use actix_web::http::{header, StatusCode};
+    use actix_web::test::{self, TestRequest};
+    use serde_json::json;
+
+    #[actix_web::test]
+    async fn replies_are_nested_under_their_parent() {
+        let pool = test_pool();
+        let (alice, alice_auth) = create_user(&pool, "alice").await;
+        let post = create_post(&pool, &alice, "Hello", true).await;
+        let app = test_app!(pool);
+
+        let req = TestRequest::post()
+            .uri(&format!("/api/posts/{}/comments", post.id))
+            .insert_header((header::AUTHORIZATION, alice_auth.as_str()))
+            .set_json(json!({ "body": "First" }))
+            .to_request();
+        let (status, parent) = send(&app, req).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let req = TestRequest::post()
+            .uri(&format!("/api/posts/{}/comments", post.id))
+            .insert_header((header::AUTHORIZATION, alice_auth.as_str()))
+            .set_json(json!({ "body": "Reply", "parent_id": parent["id"] }))
+            .to_request();
+        let (status, _) = send(&app, req).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let req = TestRequest::get().uri(&format!("/api/posts/{}/comments", post.id)).to_request();
+        let (status, page) = send(&app, req).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(page["items"].as_array().unwrap().len(), 1);
+        assert_eq!(page["items"][0]["comment"]["body"], "First");
+        assert_eq!(page["items"][0]["replies"][0]["comment"]["body"], "Reply");
+        assert_eq!(page["items"][0]["replies"][0]["user"]["username"], "alice");
+
+        let req = TestRequest::get().uri(&format!("/api/users/{}/comments", alice.id)).to_request();
+        let (status, page) = send(&app, req).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(page["items"].as_array().unwrap().len(), 2);
+    }
+
+    #[actix_web::test]
+    async fn reply_to_a_comment_of_another_post_is_bad_request() {
+        let pool = test_pool();
+        let (alice, alice_auth) = create_user(&pool, "alice").await;
+        let first = create_post(&pool, &alice, "First", true).await;
+        let second = create_post(&pool, &alice, "Second", true).await;
+        let app = test_app!(pool);
+
+        let req = TestRequest::post()
+            .uri(&format!("/api/posts/{}/comments", first.id))
+            .insert_header((header::AUTHORIZATION, alice_auth.as_str()))
+            .set_json(json!({ "body": "First" }))
+            .to_request();
+        let (_, comment) = send(&app, req).await;
+
+        let req = TestRequest::post()
+            .uri(&format!("/api/posts/{}/comments", second.id))
+            .insert_header((header::AUTHORIZATION, alice_auth.as_str()))
+            .set_json(json!({ "body": "Reply", "parent_id": comment["id"] }))
+            .to_request();
+        let (status, body) = send(&app, req).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["err"], "The parent comment does not belong to this post");
+    }
+
+    #[actix_web::test]
+    async fn add_comment_requires_a_token() {
+        let pool = test_pool();
+        let (alice, _) = create_user(&pool, "alice").await;
+        let post = create_post(&pool, &alice, "Hello", true).await;
+        let app = test_app!(pool);
+
+        let req = TestRequest::post()
+            .uri(&format!("/api/posts/{}/comments", post.id))
+            .insert_header((header::AUTHORIZATION, "Bearer not-a-token"))
+            .set_json(json!({ "body": "First" }))
+            .to_request();
+        let (status, _) = send(&app, req).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    async fn suspicious_comments_wait_for_approval() {
+        let pool = test_pool();
+        let (alice, alice_auth) = create_user(&pool, "alice").await;
+        let (_, bob_auth) = create_user(&pool, "bob").await;
+        let post = create_post(&pool, &alice, "Hello", true).await;
+        let app = test_app!(pool);
+        let comment = |auth: &str, body: &str| {
+            TestRequest::post()
+                .uri(&format!("/api/posts/{}/comments", post.id))
+                .insert_header((header::AUTHORIZATION, auth))
+                .set_json(json!({ "body": body }))
+                .to_request()
+        };
+
+        let links = "Cheap https://a.example https://b.example https://c.example";
+        let (status, body) = send(&app, comment(bob_auth.as_str(), links)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["status"], "pending");
+
+        let repeated = "Great post, visit my profile for more";
+        let (_, body) = send(&app, comment(bob_auth.as_str(), repeated)).await;
+        assert_eq!(body["status"], "approved");
+        let (_, body) = send(&app, comment(alice_auth.as_str(), repeated)).await;
+        assert_eq!(body["status"], "approved");
+        let (_, body) = send(&app, comment(bob_auth.as_str(), repeated)).await;
+        assert_eq!(body["status"], "pending");
+
+        let (_, body) = send(&app, comment(alice_auth.as_str(), "Thanks!")).await;
+        assert_eq!(body["status"], "approved");
+        let (_, body) = send(&app, comment(bob_auth.as_str(), "Thanks!")).await;
+        assert_eq!(body["status"], "approved");
+
+        let req = TestRequest::get().uri(&format!("/api/posts/{}/comments", post.id)).to_request();
+        let (_, page) = send(&app, req).await;
+        assert_eq!(page["items"].as_array().unwrap().len(), 4);
+    }
+
+    #[actix_web::test]
+    async fn add_comment_is_rate_limited_per_user() {
+        let pool = test_pool();
+        let (alice, alice_auth) = create_user(&pool, "alice").await;
+        let post = create_post(&pool, &alice, "Hello", true).await;
+        let app = test_app!(pool);
+
+        for n in 0..=LimitsConfig::default().create_comment.burst {
+            let req = TestRequest::post()
+                .uri(&format!("/api/posts/{}/comments", post.id))
+                .insert_header((header::AUTHORIZATION, alice_auth.as_str()))
+                .set_json(json!({ "body": format!("Comment {}", n) }))
+                .to_request();
+            let res = test::call_service(&app, req).await;
+            if n < LimitsConfig::default().create_comment.burst {
+                assert_eq!(res.status(), StatusCode::OK);
+            } else {
+                assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+                assert!(res.headers().contains_key(header::RETRY_AFTER));
+            }
+        }
+    }
+
+    #[actix_web::test]
+    async fn moderated_comments_wait_for_the_author() {
+        let pool = test_pool();
+        let (alice, alice_auth) = create_user(&pool, "alice").await;
+        let (_, bob_auth) = create_user(&pool, "bob").await;
+        let post = create_post(&pool, &alice, "Hello", true).await;
+        let app = test_app!(pool);
+
+        let req = TestRequest::patch()
+            .uri(&format!("/api/posts/{}", post.id))
+            .insert_header((header::AUTHORIZATION, alice_auth.as_str()))
+            .set_json(json!({ "comments_moderated": true }))
+            .to_request();
+        let (status, _) = send(&app, req).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let req = TestRequest::post()
+            .uri(&format!("/api/posts/{}/comments", post.id))
+            .insert_header((header::AUTHORIZATION, bob_auth.as_str()))
+            .set_json(json!({ "body": "First" }))
+            .to_request();
+        let (_, comment) = send(&app, req).await;
+        assert_eq!(comment["status"], "pending");
+
+        let comments = |auth: Option<&str>| {
+            let mut req = TestRequest::get().uri(&format!("/api/posts/{}/comments", post.id));
+            if let Some(auth) = auth {
+                req = req.insert_header((header::AUTHORIZATION, auth));
+            }
+            req.to_request()
+        };
+        let (_, page) = send(&app, comments(None)).await;
+        assert_eq!(page["items"].as_array().unwrap().len(), 0);
+        let (_, page) = send(&app, comments(Some(alice_auth.as_str()))).await;
+        assert_eq!(page["items"][0]["comment"]["id"], comment["id"]);
+
+        let req = TestRequest::get()
+            .uri("/api/comments/pending")
+            .insert_header((header::AUTHORIZATION, alice_auth.as_str()))
+            .to_request();
+        let (status, page) = send(&app, req).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(page["items"][0][0]["id"], comment["id"]);
+        assert_eq!(page["items"][0][1]["username"], "bob");
+
+        let approve = |auth: &str| {
+            TestRequest::post()
+                .uri(&format!("/api/comments/{}/approve", comment["id"]))
+                .insert_header((header::AUTHORIZATION, auth))
+                .to_request()
+        };
+        let (status, _) = send(&app, approve(bob_auth.as_str())).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, body) = send(&app, approve(alice_auth.as_str())).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["status"], "approved");
+
+        let (_, page) = send(&app, comments(None)).await;
+        assert_eq!(page["items"][0]["comment"]["id"], comment["id"]);
+    }
+
+    #[actix_web::test]
+    async fn closed_and_unpublished_posts_refuse_comments() {
+        let pool = test_pool();
+        let (alice, alice_auth) = create_user(&pool, "alice").await;
+        let (_, bob_auth) = create_user(&pool, "bob").await;
+        let published = create_post(&pool, &alice, "Published", true).await;
+        let draft = create_post(&pool, &alice, "Draft", false).await;
+        let app = test_app!(pool);
+
+        let req = TestRequest::post()
+            .uri(&format!("/api/posts/{}/comments", draft.id))
+            .insert_header((header::AUTHORIZATION, bob_auth.as_str()))
+            .set_json(json!({ "body": "First" }))
+            .to_request();
+        let (status, _) = send(&app, req).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let req = TestRequest::patch()
+            .uri(&format!("/api/posts/{}", published.id))
+            .insert_header((header::AUTHORIZATION, alice_auth.as_str()))
+            .set_json(json!({ "comments_closed": true }))
+            .to_request();
+        send(&app, req).await;
+
+        let req = TestRequest::post()
+            .uri(&format!("/api/posts/{}/comments", published.id))
+            .insert_header((header::AUTHORIZATION, bob_auth.as_str()))
+            .set_json(json!({ "body": "First" }))
+            .to_request();
+        let (status, body) = send(&app, req).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(body["err"], "Comments are closed on this post");
+    }
+
+    #[actix_web::test]
+    async fn user_comments_skip_unpublished_posts() {
+        let pool = test_pool();
+        let (alice, alice_auth) = create_user(&pool, "alice").await;
+        let (bob, bob_auth) = create_user(&pool, "bob").await;
+        let post = create_post(&pool, &alice, "Hello", true).await;
+        let app = test_app!(pool);
+
+        let req = TestRequest::post()
+            .uri(&format!("/api/posts/{}/comments", post.id))
+            .insert_header((header::AUTHORIZATION, bob_auth.as_str()))
+            .set_json(json!({ "body": "First" }))
+            .to_request();
+        send(&app, req).await;
+        let req = TestRequest::patch()
+            .uri(&format!("/api/posts/{}", post.id))
+            .insert_header((header::AUTHORIZATION, alice_auth.as_str()))
+            .set_json(json!({ "published": false }))
+            .to_request();
+        send(&app, req).await;
+
+        let comments = |auth: Option<&str>| {
+            let mut req = TestRequest::get().uri(&format!("/api/users/{}/comments", bob.id));
+            if let Some(auth) = auth {
+                req = req.insert_header((header::AUTHORIZATION, auth));
+            }
+            req.to_request()
+        };
+        let (_, page) = send(&app, comments(None)).await;
+        assert_eq!(page["items"].as_array().unwrap().len(), 0);
+        let (_, page) = send(&app, comments(Some(bob_auth.as_str()))).await;
+        assert_eq!(page["items"].as_array().unwrap().len(), 0);
+        let (_, page) = send(&app, comments(Some(alice_auth.as_str()))).await;
+        assert_eq!(page["items"].as_array().unwrap().len(), 1);
+    }
+
+    #[actix_web::test]
+    async fn threads_cut_by_the_limits_are_marked() {
+        let pool = test_pool();
+        let (alice, _) = create_user(&pool, "alice").await;
+        let deep = create_post(&pool, &alice, "Deep", true).await;
+        let wide = create_post(&pool, &alice, "Wide", true).await;
+        {
+            let connection: &mut DbConnection = &mut pool.get().await.unwrap();
+            let mut parent = None;
+            for level in 0..=MAX_REPLY_LEVELS + 1 {
+                let body = format!("Level {}", level);
+                parent = Some(models::create_comment(connection, &alice, deep.id, parent, &body).await.unwrap().id);
+            }
+            let root = models::create_comment(connection, &alice, wide.id, None, "Root").await.unwrap();
+            for reply in 0..=MAX_THREAD_REPLIES {
+                let body = format!("Reply {}", reply);
+                models::create_comment(connection, &alice, wide.id, Some(root.id), &body).await.unwrap();
+            }
+        }
+        let app = test_app!(pool);
+
+        let req = TestRequest::get().uri(&format!("/api/posts/{}/comments?depth=1", deep.id)).to_request();
+        let (status, page) = send(&app, req).await;
+        assert_eq!(status, StatusCode::OK);
+        let replies = page["items"][0]["replies"].as_array().unwrap();
+        assert_eq!(replies.len(), MAX_REPLY_LEVELS);
+        assert_eq!(page["items"][0]["has_more_replies"], false);
+        let marked: Vec<_> = replies.iter().filter(|reply| reply["has_more_replies"] == true).collect();
+        assert_eq!(marked.len(), 1);
+        assert_eq!(marked[0]["comment"]["body"], format!("Level {}", MAX_REPLY_LEVELS));
+
+        let req = TestRequest::get().uri(&format!("/api/posts/{}/comments", wide.id)).to_request();
+        let (_, page) = send(&app, req).await;
+        assert_eq!(page["items"][0]["replies"].as_array().unwrap().len(), MAX_THREAD_REPLIES);
+        assert_eq!(page["items"][0]["has_more_replies"], true);
+    }
+}
@@ -0,0 +1,220 @@
+use crate::errors::AppError;
+use crate::openapi;
+use actix_web::{web, HttpResponse};
+use askama::Template;
+
+#[derive(Template)]
+#[template(path = "docs.html")]
+struct DocsPage;
+
+async fn openapi_document() -> HttpResponse {
+    HttpResponse::Ok().json(openapi::document())
+}
+
+async fn docs() -> Result<HttpResponse, AppError> {
+    let html = DocsPage.render()?;
+    Ok(HttpResponse::Ok().content_type("text/html; charset=utf-8").body(html))
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/openapi.json").route(web::get().to(openapi_document)))
+        .service(web::resource("/docs").route(web::get().to(docs)));
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use crate::openapi;
+    use crate::test_utils::{send, test_pool};
+    use actix_web::http::{header, StatusCode};
+    use actix_web::test::{self, TestRequest};
+    use jsonschema::{Draft, JSONSchema};
+    use serde_json::{json, Value};
+    use std::collections::BTreeSet;
+
+    /// Checks response bodies against the schemas of the document and keeps
+    /// track of the operations checked so far.
+    struct Conformance {
+        document: Value,
+        checked: BTreeSet<(String, String)>,
+    }
+
+    /// Forbids the properties an object schema doesn't list, so that a field
+    /// added to a response without documenting it fails the check too.
+    fn close_objects(schema: &mut Value) {
+        match schema {
+            Value::Object(object) => {
+                if object.contains_key("properties") && !object.contains_key("additionalProperties") {
+                    object.insert(String::from("additionalProperties"), Value::Bool(false));
+                }
+                object.values_mut().for_each(close_objects);
+            }
+            Value::Array(items) => items.iter_mut().for_each(close_objects),
+            _ => {}
+        }
+    }
+
+    impl Conformance {
+        fn new() -> Self {
+            let mut document = openapi::document();
+            close_objects(&mut document["components"]);
+            Conformance { document, checked: BTreeSet::new() }
+        }
+
+        fn check(&mut self, method: &str, path: &str, status: StatusCode, body: &Value) -> Value {
+            let operation = &self.document["paths"][path][method];
+            assert!(operation.is_object(), "{} {} is not documented", method, path);
+            let schema = if status == StatusCode::OK {
+                // An empty list would pass whatever its items look like.
+                let items = body.get("items").unwrap_or(body);
+                assert!(items.as_array().is_none_or(|items| !items.is_empty()), "{} {} listed nothing", method, path);
+                self.checked.insert((method.to_string(), path.to_string()));
+                &operation["responses"]["200"]["content"]["application/json"]["schema"]
+            } else {
+                &operation["responses"]["default"]["content"]["application/json"]["schema"]
+            };
+
+            let schema = json!({
+                "$schema": "https://json-schema.org/draft/2020-12/schema",
+                "components": self.document["components"],
+                "allOf": [schema],
+            });
+            let compiled = JSONSchema::options().with_draft(Draft::Draft202012).compile(&schema).unwrap();
+            if let Err(errors) = compiled.validate(body) {
+                let errors: Vec<String> = errors.map(|err| format!("{} at {}", err, err.instance_path)).collect();
+                panic!("{} {} ({}) doesn't match the document:\n{}", method, path, status, errors.join("\n"));
+            }
+
+            body.clone()
+        }
+
+        /// Operations of the document with a JSON response that were not checked.
+        fn unchecked(&self) -> Vec<String> {
+            let mut unchecked = Vec::new();
+            for (path, item) in self.document["paths"].as_object().unwrap() {
+                for (method, operation) in item.as_object().unwrap() {
+                    let json = operation["responses"]["200"]["content"].get("application/json").is_some();
+                    if json && !self.checked.contains(&(method.clone(), path.clone())) {
+                        unchecked.push(format!("{} {}", method, path));
+                    }
+                }
+            }
+
+            unchecked
+        }
+    }
+
+    #[actix_web::test]
+    async fn openapi_document_is_served() {
+        let pool = test_pool();
+        let app = test_app!(pool);
+
+        let (status, body) = send(&app, TestRequest::get().uri("/openapi.json").to_request()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["openapi"], "3.1.0");
+        assert!(body["paths"]["/api/posts/{id}"]["patch"]["security"].is_array());
+    }
+
+    #[actix_web::test]
+    async fn docs_page_is_served() {
+        let pool = test_pool();
+        let app = test_app!(pool);
+
+        let res = test::call_service(&app, TestRequest::get().uri("/docs").to_request()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let body = String::from_utf8(test::read_body(res).await.to_vec()).unwrap();
+        assert!(body.contains("/openapi.json"));
+    }
+
+    #[actix_web::test]
+    async fn responses_match_the_document() {
+        let pool = test_pool();
+        let app = test_app!(pool);
+        let mut doc = Conformance::new();
+
+        macro_rules! call {
+            ($method:ident, $path:expr, $uri:expr) => {
+                call!($method, $path, $uri, None::<&str>, None::<Value>)
+            };
+            ($method:ident, $path:expr, $uri:expr, $auth:expr) => {
+                call!($method, $path, $uri, Some($auth), None::<Value>)
+            };
+            ($method:ident, $path:expr, $uri:expr, $auth:expr, $body:expr) => {{
+                let mut req = TestRequest::$method().uri(&$uri);
+                if let Some(auth) = $auth {
+                    req = req.insert_header((header::AUTHORIZATION, auth));
+                }
+                if let Some(body) = $body {
+                    req = req.set_json(body);
+                }
+                let (status, body) = send(&app, req.to_request()).await;
+                doc.check(stringify!($method), $path, status, &body)
+            }};
+        }
+
+        let mut tokens = Vec::new();
+        for username in ["alice", "bob"] {
+            let credentials = json!({ "username": username, "password": "correct horse" });
+            call!(post, "/api/users", "/api/users", None::<&str>, Some(credentials.clone()));
+            let session = call!(post, "/api/sessions", "/api/sessions", None::<&str>, Some(credentials));
+            tokens.push((session["user"]["id"].as_i64().unwrap(), format!("Bearer {}", session["token"].as_str().unwrap())));
+        }
+        let (alice, alice_auth) = (tokens[0].0, tokens[0].1.as_str());
+        let (bob, bob_auth) = (tokens[1].0, tokens[1].1.as_str());
+        call!(get, "/api/users/{id}", format!("/api/users/{}", alice));
+        call!(get, "/api/users/find/{name}", "/api/users/find/alice");
+
+        let post = call!(
+            post,
+            "/api/users/{id}/posts",
+            format!("/api/users/{}/posts", alice),
+            Some(alice_auth),
+            Some(json!({ "title": "Crabs", "body": "All about crabs" }))
+        );
+        let post = post["id"].as_i64().unwrap();
+        let changes = json!({ "body": "All about crabs\nand more\n", "comments_moderated": true });
+        call!(patch, "/api/posts/{id}", format!("/api/posts/{}", post), Some(alice_auth), Some(changes));
+        call!(post, "/api/posts/{id}/publish", format!("/api/posts/{}/publish", post), alice_auth);
+        for tag in ["rust", "crabs"] {
+            let tag = Some(json!({ "name": tag }));
+            call!(post, "/api/posts/{id}/tags", format!("/api/posts/{}/tags", post), Some(alice_auth), tag);
+        }
+
+        let comments = format!("/api/posts/{}/comments", post);
+        let comment = call!(post, "/api/posts/{id}/comments", comments, Some(alice_auth), Some(json!({ "body": "First" })));
+        let reply = json!({ "body": "Reply", "parent_id": comment["id"] });
+        call!(post, "/api/posts/{id}/comments", comments, Some(alice_auth), Some(reply));
+        let mut pending = Vec::new();
+        for body in ["Nice", "Meh"] {
+            let comment = call!(post, "/api/posts/{id}/comments", comments, Some(bob_auth), Some(json!({ "body": body })));
+            pending.push(comment["id"].as_i64().unwrap());
+        }
+        call!(get, "/api/comments/pending", "/api/comments/pending", alice_auth);
+        call!(post, "/api/comments/{id}/approve", format!("/api/comments/{}/approve", pending[0]), alice_auth);
+        call!(post, "/api/comments/{id}/reject", format!("/api/comments/{}/reject", pending[1]), alice_auth);
+
+        call!(get, "/api/posts", "/api/posts");
+        call!(get, "/api/users/{id}/posts", format!("/api/users/{}/posts", alice));
+        call!(get, "/api/posts/{id}", format!("/api/posts/{}", post));
+        call!(get, "/api/posts/{id}/comments", comments);
+        call!(get, "/api/users/{id}/comments", format!("/api/users/{}/comments", bob));
+        call!(get, "/api/tags", "/api/tags");
+        call!(get, "/api/posts/{id}/tags", format!("/api/posts/{}/tags", post));
+        call!(get, "/api/search", "/api/search?q=crabs");
+
+        let revisions = call!(get, "/api/posts/{id}/revisions", format!("/api/posts/{}/revisions", post), alice_auth);
+        let first = revisions["items"][1]["id"].as_i64().unwrap();
+        let revision = format!("/api/posts/{}/revisions/{}", post, first);
+        call!(get, "/api/posts/{id}/revisions/{revision_id}/diff", format!("{}/diff", revision), alice_auth);
+        call!(post, "/api/posts/{id}/revisions/{revision_id}/restore", format!("{}/restore", revision), alice_auth);
+
+        call!(delete, "/api/posts/{id}/tags/{name}", format!("/api/posts/{}/tags/rust", post), alice_auth);
+        call!(get, "/healthz", "/healthz");
+        call!(get, "/readyz", "/readyz");
+        call!(get, "/openapi.json", "/openapi.json");
+        call!(get, "/api/posts/{id}", "/api/posts/404");
+        call!(delete, "/api/posts/{id}", format!("/api/posts/{}", post), alice_auth);
+
+        assert_eq!(doc.unchecked(), Vec::<String>::new());
+    }
+}
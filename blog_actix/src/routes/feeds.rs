@@ -0,0 +1,206 @@
+use crate::cache::entity_tag;
+use crate::errors::AppError;
+use crate::feeds::{Feed, SiteUrl};
+use crate::routes::{etag_matches, MAX_PAGE_LIMIT};
+use crate::{models, Pool};
+use actix_web::http::header;
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::NaiveDateTime;
+
+const DEFAULT_FEED_LIMIT: i64 = 20;
+const HTTP_DATE_FORMAT: &str = "%a, %d %b %Y %H:%M:%S GMT";
+
+#[derive(Debug, Deserialize)]
+struct FeedParams {
+    limit: Option<i64>,
+}
+
+#[derive(Clone, Copy)]
+enum Format {
+    Rss,
+    Atom,
+}
+
+/// Request data needed to build the feed and answer conditional requests.
+struct FeedRequest {
+    site_url: String,
+    feed_url: String,
+    if_none_match: Option<String>,
+    if_modified_since: Option<NaiveDateTime>,
+}
+
+impl FeedRequest {
+    fn new(req: &HttpRequest, site_url: &SiteUrl) -> Self {
+        let site_url = site_url.0.clone();
+        let feed_url = format!("{}{}", site_url, req.path());
+        let header_value = |name: header::HeaderName| {
+            req.headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(String::from)
+        };
+
+        FeedRequest {
+            site_url,
+            feed_url,
+            if_none_match: header_value(header::IF_NONE_MATCH),
+            if_modified_since: header_value(header::IF_MODIFIED_SINCE)
+                .and_then(|since| NaiveDateTime::parse_from_str(since.as_str(), HTTP_DATE_FORMAT).ok()),
+        }
+    }
+
+    fn is_fresh(&self, etag: &str, updated: Option<NaiveDateTime>) -> bool {
+        if let Some(if_none_match) = &self.if_none_match {
+            return etag_matches(if_none_match, etag);
+        }
+
+        match (self.if_modified_since, updated) {
+            (Some(since), Some(updated)) => updated.and_utc().timestamp() <= since.and_utc().timestamp(),
+            _ => false,
+        }
+    }
+}
+
+fn respond(request: FeedRequest, format: Format, feed: Feed) -> HttpResponse {
+    let (content_type, body) = match format {
+        Format::Rss => ("application/rss+xml; charset=utf-8", feed.to_rss()),
+        Format::Atom => ("application/atom+xml; charset=utf-8", feed.to_atom()),
+    };
+    let etag = entity_tag(body.as_bytes());
+    let updated = feed.updated();
+    let fresh = request.is_fresh(etag.as_str(), updated);
+
+    let mut builder = if fresh {
+        HttpResponse::NotModified()
+    } else {
+        HttpResponse::Ok()
+    };
+    builder.insert_header((header::ETAG, etag.as_str()));
+    if let Some(updated) = updated {
+        builder.insert_header((header::LAST_MODIFIED, updated.format(HTTP_DATE_FORMAT).to_string()));
+    }
+
+    if fresh {
+        return builder.finish();
+    }
+
+    builder.content_type(content_type).body(body)
+}
+
+async fn feed(req: HttpRequest, format: Format, user_id: Option<i32>, params: web::Query<FeedParams>, site_url: web::Data<SiteUrl>, pool: web::Data<Pool>) -> Result<HttpResponse, AppError> {
+    let request = FeedRequest::new(&req, &site_url);
+    let site_url = request.site_url.clone();
+    let feed_url = request.feed_url.clone();
+
+    let mut conn = pool.get().await?;
+    let limit = params.limit.unwrap_or(DEFAULT_FEED_LIMIT);
+    if !(1..=MAX_PAGE_LIMIT).contains(&limit) {
+        return Err(AppError::InvalidPage);
+    }
+
+    let title = match user_id {
+        Some(id) => format!("Posts by {}", models::find_user(&mut conn, models::UserKey::Id(id)).await?.username),
+        None => String::from("Blog"),
+    };
+    let entries = models::published_posts(&mut conn, user_id, limit).await?;
+
+    let feed = Feed { title, site_url, feed_url, entries };
+
+    Ok(respond(request, format, feed))
+}
+
+async fn rss_feed(req: HttpRequest, params: web::Query<FeedParams>, site_url: web::Data<SiteUrl>, pool: web::Data<Pool>) -> Result<HttpResponse, AppError> {
+    feed(req, Format::Rss, None, params, site_url, pool).await
+}
+
+async fn atom_feed(req: HttpRequest, params: web::Query<FeedParams>, site_url: web::Data<SiteUrl>, pool: web::Data<Pool>) -> Result<HttpResponse, AppError> {
+    feed(req, Format::Atom, None, params, site_url, pool).await
+}
+
+async fn user_atom_feed(req: HttpRequest, user_id: web::Path<i32>, params: web::Query<FeedParams>, site_url: web::Data<SiteUrl>, pool: web::Data<Pool>) -> Result<HttpResponse, AppError> {
+    feed(req, Format::Atom, Some(user_id.into_inner()), params, site_url, pool).await
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/feed.rss").route(web::get().to(rss_feed)))
+        .service(web::resource("/feed.atom").route(web::get().to(atom_feed)))
+        .service(web::resource("/users/{id}/feed.atom").route(web::get().to(user_atom_feed)));
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use crate::test_utils::{create_post, create_user, test_pool};
+    use actix_web::http::{header, StatusCode};
+    use actix_web::test::{self, TestRequest};
+
+    #[actix_web::test]
+    async fn feeds_list_published_posts() {
+        let pool = test_pool();
+        let (alice, _) = create_user(&pool, "alice").await;
+        create_post(&pool, &alice, "Published", true).await;
+        create_post(&pool, &alice, "Draft", false).await;
+        let app = test_app!(pool);
+
+        for uri in &["/feed.rss".to_string(), "/feed.atom".to_string(), format!("/users/{}/feed.atom", alice.id)] {
+            let res = test::call_service(&app, TestRequest::get().uri(uri).to_request()).await;
+            assert_eq!(res.status(), StatusCode::OK, "{}", uri);
+
+            let body = String::from_utf8(test::read_body(res).await.to_vec()).unwrap();
+            assert!(body.contains("Published"), "{}", uri);
+            assert!(!body.contains("Draft"), "{}", uri);
+        }
+    }
+
+    #[actix_web::test]
+    async fn feed_links_use_the_configured_site_url() {
+        let pool = test_pool();
+        let (alice, _) = create_user(&pool, "alice").await;
+        let post = create_post(&pool, &alice, "Published", true).await;
+        let app = test_app!(pool);
+
+        let req = TestRequest::get().uri("/feed.rss").insert_header((header::HOST, "evil.example")).to_request();
+        let body = test::read_body(test::call_service(&app, req).await).await;
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains(&format!("http://blog.test/posts/{}", post.id)), "{}", body);
+        assert!(!body.contains("evil.example"), "{}", body);
+    }
+
+    #[actix_web::test]
+    async fn feed_answers_conditional_request_with_not_modified() {
+        let pool = test_pool();
+        let (alice, _) = create_user(&pool, "alice").await;
+        create_post(&pool, &alice, "Published", true).await;
+        let app = test_app!(pool);
+
+        let res = test::call_service(&app, TestRequest::get().uri("/feed.atom").to_request()).await;
+        let etag = res.headers().get(header::ETAG).unwrap().clone();
+
+        let req = TestRequest::get().uri("/feed.atom").insert_header((header::IF_NONE_MATCH, etag)).to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+    }
+
+    #[actix_web::test]
+    async fn empty_feed_keeps_its_etag() {
+        let pool = test_pool();
+        let app = test_app!(pool);
+
+        let res = test::call_service(&app, TestRequest::get().uri("/feed.atom").to_request()).await;
+        let etag = res.headers().get(header::ETAG).unwrap().clone();
+        let body = test::read_body(res).await;
+        assert!(String::from_utf8(body.to_vec()).unwrap().contains("<updated>1970-01-01T00:00:00+00:00</updated>"));
+
+        let req = TestRequest::get().uri("/feed.atom").insert_header((header::IF_NONE_MATCH, etag)).to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+    }
+
+    #[actix_web::test]
+    async fn feed_of_missing_user_is_not_found() {
+        let pool = test_pool();
+        let app = test_app!(pool);
+
+        let res = test::call_service(&app, TestRequest::get().uri("/users/42/feed.atom").to_request()).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,75 @@
+use crate::errors::AppError;
+use crate::Pool;
+use actix_web::{web, HttpResponse};
+use diesel_async::RunQueryDsl;
+
+#[derive(Debug, Serialize)]
+struct PoolStatus {
+    connections: usize,
+    idle_connections: usize,
+    max_size: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct HealthResponse {
+    status: &'static str,
+    pool: PoolStatus,
+}
+
+impl HealthResponse {
+    fn new(status: &'static str, pool: &Pool) -> Self {
+        let state = pool.status();
+        HealthResponse {
+            status,
+            pool: PoolStatus {
+                connections: state.size,
+                idle_connections: state.available,
+                max_size: state.max_size,
+            },
+        }
+    }
+}
+
+/// Liveness: the server answers requests, whatever the state of the database.
+async fn healthz(pool: web::Data<Pool>) -> HttpResponse {
+    HttpResponse::Ok().json(HealthResponse::new("ok", &pool))
+}
+
+async fn ping(pool: &Pool) -> Result<(), AppError> {
+    let mut conn = pool.get().await?;
+    diesel::sql_query("SELECT 1").execute(&mut conn).await?;
+    Ok(())
+}
+
+/// Readiness: a connection can be taken from the pool and used.
+async fn readyz(pool: web::Data<Pool>) -> HttpResponse {
+    match ping(&pool).await {
+        Ok(()) => HttpResponse::Ok().json(HealthResponse::new("ok", &pool)),
+        Err(_) => HttpResponse::ServiceUnavailable().json(HealthResponse::new("unavailable", &pool)),
+    }
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/healthz").route(web::get().to(healthz)))
+        .service(web::resource("/readyz").route(web::get().to(readyz)));
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use crate::test_utils::{send, test_pool};
+    use actix_web::http::StatusCode;
+    use actix_web::test::TestRequest;
+
+    #[actix_web::test]
+    async fn health_checks_report_the_pool() {
+        let pool = test_pool();
+        let app = test_app!(pool);
+
+        for uri in &["/healthz", "/readyz"] {
+            let (status, body) = send(&app, TestRequest::get().uri(uri).to_request()).await;
+            assert_eq!(status, StatusCode::OK, "{}", uri);
+            assert_eq!(body["status"], "ok");
+            assert_eq!(body["pool"]["max_size"], 1);
+        }
+    }
+}
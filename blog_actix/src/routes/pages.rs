@@ -0,0 +1,180 @@
+use crate::errors::AppError;
+use crate::routes::comments::DEFAULT_THREAD_DEPTH;
+use crate::routes::{encode_cursor, PageParams};
+use crate::{models, Pool};
+use actix_web::{web, HttpResponse};
+use askama::Template;
+
+struct PostSummary {
+    id: i32,
+    user_id: i32,
+    username: String,
+    title: String,
+    body_html: String,
+    comment_count: usize,
+}
+
+struct CommentView {
+    username: String,
+    body_html: String,
+    indent: usize,
+    has_more_replies: bool,
+}
+
+#[derive(Template)]
+#[template(path = "index.html")]
+struct IndexPage {
+    posts: Vec<PostSummary>,
+    next_cursor: Option<String>,
+}
+
+#[derive(Template)]
+#[template(path = "post.html")]
+struct PostPage {
+    post: models::Post,
+    author: models::User,
+    comments: Vec<CommentView>,
+    next_cursor: Option<String>,
+}
+
+#[derive(Template)]
+#[template(path = "user.html")]
+struct UserPage {
+    user: models::User,
+    posts: Vec<PostSummary>,
+    next_cursor: Option<String>,
+}
+
+fn render<T: Template>(page: T) -> Result<HttpResponse, AppError> {
+    let html = page.render()?;
+    Ok(HttpResponse::Ok().content_type("text/html; charset=utf-8").body(html))
+}
+
+fn flatten_threads(threads: Vec<models::CommentThread>, depth: usize, views: &mut Vec<CommentView>) {
+    for thread in threads {
+        views.push(CommentView {
+            username: thread.user.username,
+            body_html: thread.comment.body_html,
+            indent: depth * 2,
+            has_more_replies: thread.has_more_replies,
+        });
+        flatten_threads(thread.replies, depth + 1, views);
+    }
+}
+
+async fn index(params: web::Query<PageParams>, pool: web::Data<Pool>) -> Result<HttpResponse, AppError> {
+    let mut conn = pool.get().await?;
+    let page = models::all_posts(&mut conn, &params.to_request()?, None, None).await?;
+
+    let posts = page
+        .items
+        .into_iter()
+        .map(|((post, user), comments, _)| PostSummary {
+            id: post.id,
+            user_id: user.id,
+            username: user.username,
+            title: post.title,
+            body_html: post.body_html,
+            comment_count: comments.len(),
+        })
+        .collect();
+
+    let page = IndexPage { posts, next_cursor: page.next.map(encode_cursor) };
+
+    render(page)
+}
+
+async fn post(post_id: web::Path<i32>, params: web::Query<PageParams>, pool: web::Data<Pool>) -> Result<HttpResponse, AppError> {
+    let mut conn = pool.get().await?;
+    let post = models::find_visible_post(&mut conn, post_id.into_inner(), None).await?;
+
+    let author = models::find_user(&mut conn, models::UserKey::Id(post.user_id)).await?;
+    let threads = models::post_comments(&mut conn, post.id, &params.to_request()?, DEFAULT_THREAD_DEPTH, None).await?;
+
+    let mut comments = Vec::new();
+    flatten_threads(threads.items, 0, &mut comments);
+
+    let page = PostPage { post, author, comments, next_cursor: threads.next.map(encode_cursor) };
+
+    render(page)
+}
+
+async fn user(user_id: web::Path<i32>, params: web::Query<PageParams>, pool: web::Data<Pool>) -> Result<HttpResponse, AppError> {
+    let mut conn = pool.get().await?;
+    let user = models::find_user(&mut conn, models::UserKey::Id(user_id.into_inner())).await?;
+    let page = models::user_posts(&mut conn, user.id, &params.to_request()?, None, None).await?;
+
+    let posts = page
+        .items
+        .into_iter()
+        .map(|(post, comments, _)| PostSummary {
+            id: post.id,
+            user_id: user.id,
+            username: user.username.clone(),
+            title: post.title,
+            body_html: post.body_html,
+            comment_count: comments.len(),
+        })
+        .collect();
+
+    let page = UserPage { user, posts, next_cursor: page.next.map(encode_cursor) };
+
+    render(page)
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/").route(web::get().to(index)))
+        .service(web::resource("/posts/{id}").route(web::get().to(post)))
+        .service(web::resource("/users/{id}").route(web::get().to(user)));
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use crate::test_utils::{create_post, create_user, test_pool};
+    use actix_web::http::StatusCode;
+    use actix_web::test::{self, TestRequest};
+
+    #[actix_web::test]
+    async fn pages_render_published_posts() {
+        let pool = test_pool();
+        let (alice, _) = create_user(&pool, "alice").await;
+        let post = create_post(&pool, &alice, "Published", true).await;
+        let app = test_app!(pool);
+
+        for uri in &["/".to_string(), format!("/posts/{}", post.id), format!("/users/{}", alice.id)] {
+            let res = test::call_service(&app, TestRequest::get().uri(uri).to_request()).await;
+            assert_eq!(res.status(), StatusCode::OK, "{}", uri);
+
+            let body = String::from_utf8(test::read_body(res).await.to_vec()).unwrap();
+            assert!(body.contains("Published"), "{}", uri);
+        }
+    }
+
+    #[actix_web::test]
+    async fn unpublished_post_page_is_not_found() {
+        let pool = test_pool();
+        let (alice, _) = create_user(&pool, "alice").await;
+        let draft = create_post(&pool, &alice, "Draft", false).await;
+        let app = test_app!(pool);
+
+        let res = test::call_service(&app, TestRequest::get().uri(&format!("/posts/{}", draft.id)).to_request()).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn user_page_skips_drafts_before_the_limit() {
+        let pool = test_pool();
+        let (alice, _) = create_user(&pool, "alice").await;
+        create_post(&pool, &alice, "Published", true).await;
+        create_post(&pool, &alice, "Draft", false).await;
+        let app = test_app!(pool);
+
+        let uri = format!("/users/{}?limit=1", alice.id);
+        let res = test::call_service(&app, TestRequest::get().uri(&uri).to_request()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let body = String::from_utf8(test::read_body(res).await.to_vec()).unwrap();
+        assert!(body.contains("Published"));
+        assert!(!body.contains("Older posts"));
+    }
+}
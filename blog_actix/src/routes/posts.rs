@@ -1,64 +1,588 @@
+use crate::auth::{self, AuthUser};
+use crate::cache::{Listing, ListingCache, ListingKey};
+use crate::config::LimitsConfig;
 use crate::errors::AppError;
-use crate::routes::convert;
+use crate::routes::tags::normalize_tag;
+use crate::routes::{etag_matches, json_config, PageParams, PageResponse};
+use crate::validation::{not_blank, single_line};
 use crate::{models, Pool};
-use actix_web::{web, HttpResponse};
-use diesel::prelude::*;
-use futures::Future;
+use actix_web::http::header;
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{NaiveDateTime, Utc};
+use std::future::Future;
+use validator::Validate;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Validate)]
 struct PostInput {
+    #[validate(length(min = 1, max = 200), custom = "single_line")]
     title: String,
+    #[validate(length(min = 1, max = 50000), custom = "not_blank")]
     body: String,
 }
 
-fn add_post(user_id: web::Path<i32>, post: web::Json<PostInput>, pool: web::Data<Pool>)
-    -> impl Future<Item = HttpResponse, Error = AppError> {
-        web::block(move || {
-            let connection: &SqliteConnection = &pool.get().unwrap();
-            let key = models::UserKey::Id(user_id.into_inner());
-            models::find_user(connection, key).and_then(|user| {
-                let post = post.into_inner();
-                let title = post.title;
-                let body = post.body;
-                models::create_post(connection, &user, title.as_str(), body.as_str())
-            })
-        })
-        .then(convert)
+#[derive(Debug, Deserialize)]
+struct TagFilter {
+    tag: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+struct PostUpdateInput {
+    #[validate(length(min = 1, max = 200), custom = "single_line")]
+    title: Option<String>,
+    #[validate(length(min = 1, max = 50000), custom = "not_blank")]
+    body: Option<String>,
+    published: Option<bool>,
+    comments_closed: Option<bool>,
+    comments_moderated: Option<bool>,
+    /// UTC time at which the post gets published, `null` cancels the schedule.
+    #[serde(default, deserialize_with = "present")]
+    publish_at: Option<Option<NaiveDateTime>>,
+}
+
+/// Tells a field set to `null`, `Some(None)`, from a missing one, `None`.
+fn present<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: serde::Deserialize<'de>,
+{
+    serde::Deserialize::deserialize(deserializer).map(Some)
+}
+
+async fn add_post(user_id: web::Path<i32>, post: web::Json<PostInput>, auth: AuthUser, pool: web::Data<Pool>, cache: web::Data<ListingCache>) -> Result<HttpResponse, AppError> {
+    let mut conn = pool.get().await?;
+    let user = auth.0;
+    if user.id != user_id.into_inner() {
+        return Err(AppError::Forbidden);
+    }
+
+    let post = post.into_inner();
+    post.validate()?;
+    let title = post.title;
+    let body = post.body;
+    let post = models::create_post(&mut conn, &user, title.as_str(), body.as_str()).await?;
+    cache.invalidate();
+
+    Ok(HttpResponse::Ok().json(post))
+}
+
+async fn publish_post(post_id: web::Path<i32>, auth: AuthUser, pool: web::Data<Pool>, cache: web::Data<ListingCache>) -> Result<HttpResponse, AppError> {
+    let mut conn = pool.get().await?;
+    let post = auth.authored_post(&mut conn, post_id.into_inner()).await?;
+
+    let post = models::publish_post(&mut conn, post.id).await?;
+    cache.invalidate();
+
+    Ok(HttpResponse::Ok().json(post))
 }
 
-fn publish_post(post_id: web::Path<i32>, pool: web::Data<Pool>)
-    -> impl Future<Item = HttpResponse, Error = AppError> {
-        web::block(move || {
-            let connection: &SqliteConnection = &pool.get().unwrap();
-            models::publish_post(connection, post_id.into_inner())
-        })
-        .then(convert)
+/// Drafts and scheduled posts are only found by their author.
+async fn get_post(req: HttpRequest, post_id: web::Path<i32>, pool: web::Data<Pool>) -> Result<HttpResponse, AppError> {
+    let mut conn = pool.get().await?;
+    let post = models::find_visible_post(&mut conn, post_id.into_inner(), auth::token_user_id(&req)).await?;
+
+    Ok(HttpResponse::Ok().json(post))
 }
 
-fn user_posts(user_id: web::Path<i32>, pool: web::Data<Pool>)
-    -> impl Future<Item = HttpResponse, Error = AppError> {
-        web::block(move || {
-            let connection: &SqliteConnection = &pool.get().unwrap();
-            models::user_posts(connection, user_id.into_inner())
-        })
-        .then(convert)
+async fn update_post(post_id: web::Path<i32>, input: web::Json<PostUpdateInput>, auth: AuthUser, pool: web::Data<Pool>, cache: web::Data<ListingCache>) -> Result<HttpResponse, AppError> {
+    let mut conn = pool.get().await?;
+    let post = auth.authored_post(&mut conn, post_id.into_inner()).await?;
+
+    let input = input.into_inner();
+    input.validate()?;
+    if let Some(Some(publish_at)) = input.publish_at {
+        if input.published.unwrap_or(post.published) || publish_at <= Utc::now().naive_utc() {
+            return Err(AppError::InvalidSchedule);
+        }
+    }
+    let changes = models::PostChanges {
+        title: input.title.as_deref(),
+        body: input.body.as_deref(),
+        published: input.published,
+        comments_closed: input.comments_closed,
+        comments_moderated: input.comments_moderated,
+        publish_at: input.publish_at,
+    };
+    let post = models::update_post(&mut conn, post.id, &changes).await?;
+    cache.invalidate();
+
+    Ok(HttpResponse::Ok().json(post))
 }
 
-fn all_posts(pool: web::Data<Pool>)
-    -> impl Future<Item = HttpResponse, Error = AppError> {
-        web::block(move || {
-            let connection: &SqliteConnection = &pool.get().unwrap();
-            models::all_posts(connection)
-        })
-        .then(convert)
+async fn delete_post(post_id: web::Path<i32>, auth: AuthUser, pool: web::Data<Pool>, cache: web::Data<ListingCache>) -> Result<HttpResponse, AppError> {
+    let mut conn = pool.get().await?;
+    let post = auth.authored_post(&mut conn, post_id.into_inner()).await?;
+
+    let post = models::delete_post(&mut conn, post.id).await?;
+    cache.invalidate();
+
+    Ok(HttpResponse::Ok().json(post))
+}
+
+/// Answers from the cache, loading and caching the listing on a miss, with
+/// `304 Not Modified` when the client's `If-None-Match` has its entity tag.
+async fn cached_listing<F, T>(req: &HttpRequest, cache: &ListingCache, key: ListingKey, load: F) -> Result<HttpResponse, AppError>
+where
+    F: Future<Output = Result<T, AppError>>,
+    T: serde::Serialize,
+{
+    let listing = match cache.get(&key) {
+        Some(listing) => listing,
+        None => {
+            let generation = cache.generation();
+            let body = serde_json::to_vec(&load.await?).expect("Failed to serialize the listing");
+            cache.insert(key, generation, Listing::new(body))
+        }
+    };
+
+    let if_none_match = req.headers().get(header::IF_NONE_MATCH).and_then(|value| value.to_str().ok());
+    if if_none_match.is_some_and(|tags| etag_matches(tags, listing.etag.as_str())) {
+        return Ok(HttpResponse::NotModified().insert_header((header::ETAG, listing.etag.as_str())).finish());
+    }
+
+    Ok(HttpResponse::Ok()
+        .insert_header((header::ETAG, listing.etag.as_str()))
+        .content_type("application/json")
+        .body(listing.body.clone()))
 }
 
-pub fn configure(cfg: &mut web::ServiceConfig) {
+async fn user_posts(req: HttpRequest, user_id: web::Path<i32>, params: web::Query<PageParams>, filter: web::Query<TagFilter>, pool: web::Data<Pool>, cache: web::Data<ListingCache>) -> Result<HttpResponse, AppError> {
+    let user_id = user_id.into_inner();
+    let page = params.to_request()?;
+    let tag = filter.tag.as_ref().map(|tag| normalize_tag(tag));
+    let viewer = auth::token_user_id(&req);
+    let key = ListingKey::new(Some(user_id), &page, tag.as_deref(), viewer);
+
+    cached_listing(&req, &cache, key, async move {
+        let mut conn = pool.get().await?;
+        models::user_posts(&mut conn, user_id, &page, tag.as_deref(), viewer)
+            .await
+            .map(PageResponse::from)
+    })
+    .await
+}
+
+async fn all_posts(req: HttpRequest, params: web::Query<PageParams>, filter: web::Query<TagFilter>, pool: web::Data<Pool>, cache: web::Data<ListingCache>) -> Result<HttpResponse, AppError> {
+    let page = params.to_request()?;
+    let tag = filter.tag.as_ref().map(|tag| normalize_tag(tag));
+    let viewer = auth::token_user_id(&req);
+    let key = ListingKey::new(None, &page, tag.as_deref(), viewer);
+
+    cached_listing(&req, &cache, key, async move {
+        let mut conn = pool.get().await?;
+        models::all_posts(&mut conn, &page, tag.as_deref(), viewer)
+            .await
+            .map(PageResponse::from)
+    })
+    .await
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig, limits: &LimitsConfig) {
     cfg.service(
             web::resource("/users/{id}/posts")
-                .route(web::post().to_async(add_post))
-                .route(web::get().to_async(user_posts)),
+                .app_data(json_config(limits))
+                .route(web::post().to(add_post))
+                .route(web::get().to(user_posts)),
         )
-        .service(web::resource("/posts").route(web::get().to_async(all_posts)))
-        .service(web::resource("/posts/{id}/publish").route(web::post().to_async(publish_post)));
+        .service(web::resource("/posts").route(web::get().to(all_posts)))
+        .service(
+            web::resource("/posts/{id}")
+                .app_data(json_config(limits))
+                .route(web::get().to(get_post))
+                .route(web::patch().to(update_post))
+                .route(web::delete().to(delete_post)),
+        )
+        .service(web::resource("/posts/{id}/publish").route(web::post().to(publish_post)));
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use super::*;
+    use crate::auth::{self, Secret};
+    use crate::test_utils::{create_post, create_user, send, test_pool};
+    use crate::{db, migrations};
+    use actix_web::http::{header, StatusCode};
+    use actix_web::rt::System;
+    use actix_web::test::{call_and_read_body_json, call_service, init_service, TestRequest};
+    use actix_web::App;
+    use chrono::Duration;
+    use diesel::Connection;
+    use serde_json::json;
+    use std::{env, fs, process, thread};
+
+    const THREADS: usize = 8;
+    const POSTS_PER_THREAD: usize = 25;
+
+    /// Posts created at the same time on different pool connections must each
+    /// be answered with the post that request inserted.
+    #[test]
+    fn concurrent_add_post_returns_inserted_post() {
+        let path = env::temp_dir().join(format!("blog_actix_add_post_{}.sqlite", process::id()));
+        let _ = fs::remove_file(&path);
+        let mut connection = db::SyncConnection::establish(path.to_str().unwrap()).unwrap();
+        migrations::run(&mut connection).unwrap();
+        drop(connection);
+
+        let pool = db::pool_builder(path.to_str().unwrap()).max_size(THREADS).build().unwrap();
+        let user = System::new()
+            .block_on(async { models::create_user(&mut pool.get().await.unwrap(), "writer", "hash").await })
+            .unwrap();
+
+        let secret = Secret(String::from("test-secret"));
+        let token = auth::issue_token(&secret, &user).unwrap();
+        let user_id = user.id;
+
+        let workers: Vec<_> = (0..THREADS)
+            .map(|worker| {
+                let (pool, secret, token) = (pool.clone(), secret.clone(), token.clone());
+                thread::spawn(move || {
+                    System::new().block_on(async move {
+                        let app = init_service(
+                            App::new()
+                                .app_data(web::Data::new(pool))
+                                .app_data(web::Data::new(secret))
+                                .app_data(web::Data::new(ListingCache::default()))
+                                .configure(|cfg| configure(cfg, &LimitsConfig::default())),
+                        )
+                        .await;
+
+                        for n in 0..POSTS_PER_THREAD {
+                            let input = PostInput {
+                                title: format!("Post {} of worker {}", n, worker),
+                                body: format!("Body {} of worker {}", n, worker),
+                            };
+                            let req = TestRequest::post()
+                                .uri(&format!("/users/{}/posts", user_id))
+                                .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+                                .set_json(&input)
+                                .to_request();
+
+                            let post: models::Post = call_and_read_body_json(&app, req).await;
+                            assert_eq!(post.title, input.title);
+                            assert_eq!(post.body, input.body);
+                        }
+                    })
+                })
+            })
+            .collect();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[actix_web::test]
+    async fn add_post_requires_the_author_token() {
+        let pool = test_pool();
+        let (alice, alice_auth) = create_user(&pool, "alice").await;
+        let (_, bob_auth) = create_user(&pool, "bob").await;
+        let app = test_app!(pool);
+        let input = json!({ "title": "Hello", "body": "*World*" });
+
+        let req = TestRequest::post().uri(&format!("/api/users/{}/posts", alice.id)).set_json(&input).to_request();
+        let (status, _) = send(&app, req).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let req = TestRequest::post()
+            .uri(&format!("/api/users/{}/posts", alice.id))
+            .insert_header((header::AUTHORIZATION, bob_auth))
+            .set_json(&input)
+            .to_request();
+        let (status, _) = send(&app, req).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        let req = TestRequest::post()
+            .uri(&format!("/api/users/{}/posts", alice.id))
+            .insert_header((header::AUTHORIZATION, alice_auth))
+            .set_json(&input)
+            .to_request();
+        let (status, post) = send(&app, req).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(post["user_id"], alice.id);
+        assert_eq!(post["published"], false);
+        assert_eq!(post["body_html"], "<p><em>World</em></p>\n");
+    }
+
+    #[actix_web::test]
+    async fn add_post_sanitizes_the_body() {
+        let pool = test_pool();
+        let (alice, alice_auth) = create_user(&pool, "alice").await;
+        let app = test_app!(pool);
+        let body = "<script>alert(1)</script>\n\n[web](https://example.com) [ftp](ftp://example.com) [js](javascript:alert(1))";
+
+        let req = TestRequest::post()
+            .uri(&format!("/api/users/{}/posts", alice.id))
+            .insert_header((header::AUTHORIZATION, alice_auth))
+            .set_json(json!({ "title": "Links", "body": body }))
+            .to_request();
+        let (status, post) = send(&app, req).await;
+        assert_eq!(status, StatusCode::OK);
+        let html = post["body_html"].as_str().unwrap();
+        assert!(!html.contains("script"), "{}", html);
+        assert!(html.contains("href=\"https://example.com\""), "{}", html);
+        assert!(!html.contains("ftp:") && !html.contains("javascript:"), "{}", html);
+    }
+
+    #[actix_web::test]
+    async fn add_post_with_blank_title_is_unprocessable() {
+        let pool = test_pool();
+        let (alice, alice_auth) = create_user(&pool, "alice").await;
+        let app = test_app!(pool);
+
+        let req = TestRequest::post()
+            .uri(&format!("/api/users/{}/posts", alice.id))
+            .insert_header((header::AUTHORIZATION, alice_auth))
+            .set_json(json!({ "title": "", "body": "World" }))
+            .to_request();
+        let (status, body) = send(&app, req).await;
+
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(body["fields"]["title"].is_array());
+    }
+
+    #[actix_web::test]
+    async fn add_post_over_the_body_limit_is_too_large() {
+        let pool = test_pool();
+        let (alice, alice_auth) = create_user(&pool, "alice").await;
+        let app = test_app!(pool);
+
+        let body = "x".repeat(LimitsConfig::default().json_body + 1);
+        let req = TestRequest::post()
+            .uri(&format!("/api/users/{}/posts", alice.id))
+            .insert_header((header::AUTHORIZATION, alice_auth))
+            .set_json(json!({ "title": "Hello", "body": body }))
+            .to_request();
+        let (status, _) = send(&app, req).await;
+
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[actix_web::test]
+    async fn get_post_and_missing_post() {
+        let pool = test_pool();
+        let (alice, alice_auth) = create_user(&pool, "alice").await;
+        let (_, bob_auth) = create_user(&pool, "bob").await;
+        let published = create_post(&pool, &alice, "Hello", true).await;
+        let draft = create_post(&pool, &alice, "Draft", false).await;
+        let app = test_app!(pool);
+
+        let req = TestRequest::get().uri(&format!("/api/posts/{}", published.id)).to_request();
+        let (status, body) = send(&app, req).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["title"], "Hello");
+
+        let req = TestRequest::get().uri(&format!("/api/posts/{}", draft.id)).to_request();
+        let (status, _) = send(&app, req).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let req = TestRequest::get()
+            .uri(&format!("/api/posts/{}", draft.id))
+            .insert_header((header::AUTHORIZATION, bob_auth))
+            .to_request();
+        let (status, _) = send(&app, req).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let req = TestRequest::get()
+            .uri(&format!("/api/posts/{}", draft.id))
+            .insert_header((header::AUTHORIZATION, alice_auth))
+            .to_request();
+        let (status, body) = send(&app, req).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["title"], "Draft");
+
+        let req = TestRequest::get().uri("/api/posts/42").to_request();
+        let (status, _) = send(&app, req).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn publish_update_and_delete_post() {
+        let pool = test_pool();
+        let (alice, alice_auth) = create_user(&pool, "alice").await;
+        let (_, bob_auth) = create_user(&pool, "bob").await;
+        let post = create_post(&pool, &alice, "Hello", false).await;
+        let app = test_app!(pool);
+
+        let req = TestRequest::post()
+            .uri(&format!("/api/posts/{}/publish", post.id))
+            .insert_header((header::AUTHORIZATION, bob_auth.as_str()))
+            .to_request();
+        let (status, _) = send(&app, req).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        let req = TestRequest::post()
+            .uri(&format!("/api/posts/{}/publish", post.id))
+            .insert_header((header::AUTHORIZATION, alice_auth.as_str()))
+            .to_request();
+        let (status, body) = send(&app, req).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["published"], true);
+        assert!(body["published_at"].is_string());
+
+        let req = TestRequest::patch()
+            .uri(&format!("/api/posts/{}", post.id))
+            .insert_header((header::AUTHORIZATION, alice_auth.as_str()))
+            .set_json(json!({ "title": "Hello again" }))
+            .to_request();
+        let (status, body) = send(&app, req).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["title"], "Hello again");
+
+        let req = TestRequest::delete()
+            .uri(&format!("/api/posts/{}", post.id))
+            .insert_header((header::AUTHORIZATION, bob_auth.as_str()))
+            .to_request();
+        let (status, _) = send(&app, req).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        let req = TestRequest::delete()
+            .uri(&format!("/api/posts/{}", post.id))
+            .insert_header((header::AUTHORIZATION, alice_auth.as_str()))
+            .to_request();
+        let (status, _) = send(&app, req).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let req = TestRequest::get().uri(&format!("/api/posts/{}", post.id)).to_request();
+        let (status, _) = send(&app, req).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn list_posts_pages_through_published_posts() {
+        let pool = test_pool();
+        let (alice, alice_auth) = create_user(&pool, "alice").await;
+        let first = create_post(&pool, &alice, "First", true).await;
+        create_post(&pool, &alice, "Draft", false).await;
+        let last = create_post(&pool, &alice, "Last", true).await;
+        let app = test_app!(pool);
+
+        let req = TestRequest::get().uri("/api/posts?limit=1").to_request();
+        let (status, page) = send(&app, req).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(page["items"][0][0][0]["id"], last.id);
+
+        let cursor = page["next_cursor"].as_str().unwrap();
+        let req = TestRequest::get().uri(&format!("/api/posts?limit=1&cursor={}", cursor)).to_request();
+        let (_, page) = send(&app, req).await;
+        assert_eq!(page["items"][0][0][0]["id"], first.id);
+        assert!(page["next_cursor"].is_null());
+
+        let req = TestRequest::get().uri(&format!("/api/users/{}/posts", alice.id)).to_request();
+        let (status, page) = send(&app, req).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(page["items"].as_array().unwrap().len(), 2);
+
+        let req = TestRequest::get()
+            .uri(&format!("/api/users/{}/posts", alice.id))
+            .insert_header((header::AUTHORIZATION, alice_auth))
+            .to_request();
+        let (status, page) = send(&app, req).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(page["items"].as_array().unwrap().len(), 3);
+    }
+
+    #[actix_web::test]
+    async fn list_posts_with_invalid_page_is_bad_request() {
+        let pool = test_pool();
+        let app = test_app!(pool);
+
+        for uri in &["/api/posts?limit=0", "/api/posts?limit=101", "/api/posts?cursor=not-a-cursor"] {
+            let req = TestRequest::get().uri(uri).to_request();
+            let (status, _) = send(&app, req).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{}", uri);
+        }
+    }
+
+    #[actix_web::test]
+    async fn list_posts_answers_not_modified_until_a_write() {
+        let pool = test_pool();
+        let (alice, alice_auth) = create_user(&pool, "alice").await;
+        let post = create_post(&pool, &alice, "First", true).await;
+        let app = test_app!(pool);
+
+        let res = call_service(&app, TestRequest::get().uri("/api/posts").to_request()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let etag = res.headers().get(header::ETAG).unwrap().to_str().unwrap().to_string();
+
+        let req = TestRequest::get().uri("/api/posts").insert_header((header::IF_NONE_MATCH, etag.as_str())).to_request();
+        let res = call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+
+        let req = TestRequest::post()
+            .uri(&format!("/api/posts/{}/comments", post.id))
+            .insert_header((header::AUTHORIZATION, alice_auth.as_str()))
+            .set_json(json!({ "body": "Nice" }))
+            .to_request();
+        let (status, _) = send(&app, req).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let req = TestRequest::get().uri("/api/posts").insert_header((header::IF_NONE_MATCH, etag.as_str())).to_request();
+        let res = call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_ne!(res.headers().get(header::ETAG).unwrap().to_str().unwrap(), etag);
+
+        let req = TestRequest::post()
+            .uri(&format!("/api/users/{}/posts", alice.id))
+            .insert_header((header::AUTHORIZATION, alice_auth.as_str()))
+            .set_json(json!({ "title": "Second", "body": "More" }))
+            .to_request();
+        let (_, second) = send(&app, req).await;
+        let req = TestRequest::post()
+            .uri(&format!("/api/posts/{}/publish", second["id"]))
+            .insert_header((header::AUTHORIZATION, alice_auth.as_str()))
+            .to_request();
+        send(&app, req).await;
+
+        let (_, page) = send(&app, TestRequest::get().uri("/api/posts").to_request()).await;
+        assert_eq!(page["items"][0][0][0]["id"], second["id"]);
+    }
+
+    #[actix_web::test]
+    async fn scheduled_posts_are_published_when_due() {
+        let pool = test_pool();
+        let (alice, alice_auth) = create_user(&pool, "alice").await;
+        let post = create_post(&pool, &alice, "Later", false).await;
+        let app = test_app!(pool);
+
+        let schedule = |publish_at: NaiveDateTime| {
+            TestRequest::patch()
+                .uri(&format!("/api/posts/{}", post.id))
+                .insert_header((header::AUTHORIZATION, alice_auth.as_str()))
+                .set_json(json!({ "publish_at": publish_at }))
+                .to_request()
+        };
+        let (status, _) = send(&app, schedule(Utc::now().naive_utc() - Duration::hours(1))).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, body) = send(&app, schedule(Utc::now().naive_utc() + Duration::hours(1))).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["published"], false);
+        assert!(body["publish_at"].is_string());
+
+        let unschedule = TestRequest::patch()
+            .uri(&format!("/api/posts/{}", post.id))
+            .insert_header((header::AUTHORIZATION, alice_auth.as_str()))
+            .set_json(json!({ "publish_at": null }))
+            .to_request();
+        let (status, body) = send(&app, unschedule).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body["publish_at"].is_null());
+
+        {
+            let mut conn = pool.get().await.unwrap();
+            assert_eq!(models::publish_scheduled_posts(&mut conn).await.unwrap(), 0);
+
+            // Move the schedule to the past, as if the hour had gone by.
+            let changes = models::PostChanges {
+                publish_at: Some(Some(Utc::now().naive_utc() - Duration::minutes(1))),
+                ..Default::default()
+            };
+            models::update_post(&mut conn, post.id, &changes).await.unwrap();
+            assert_eq!(models::publish_scheduled_posts(&mut conn).await.unwrap(), 1);
+        }
+
+        let (_, body) = send(&app, TestRequest::get().uri(&format!("/api/posts/{}", post.id)).to_request()).await;
+        assert_eq!(body["published"], true);
+        assert!(body["published_at"].is_string());
+        assert!(body["publish_at"].is_null());
+    }
 }
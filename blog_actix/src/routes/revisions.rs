@@ -0,0 +1,157 @@
+use crate::auth::AuthUser;
+use crate::cache::ListingCache;
+use crate::diff;
+use crate::errors::AppError;
+use crate::routes::{PageParams, PageResponse};
+use crate::{models, Pool};
+use actix_web::{web, HttpResponse};
+
+#[derive(Debug, Deserialize)]
+struct DiffParams {
+    /// The revision to compare with, the newest one when unset.
+    to: Option<i32>,
+}
+
+/// Unified diffs of the title and body, empty when unchanged.
+#[derive(Debug, Serialize)]
+struct RevisionDiff {
+    from: i32,
+    to: i32,
+    title: String,
+    body: String,
+}
+
+/// Only the author of a post sees its revisions, which may predate its publication.
+async fn post_revisions(post_id: web::Path<i32>, params: web::Query<PageParams>, auth: AuthUser, pool: web::Data<Pool>) -> Result<HttpResponse, AppError> {
+    let mut conn = pool.get().await?;
+    let page = params.to_request()?;
+    let post = auth.authored_post(&mut conn, post_id.into_inner()).await?;
+    let page = models::post_revisions(&mut conn, post.id, &page).await.map(PageResponse::from)?;
+
+    Ok(HttpResponse::Ok().json(page))
+}
+
+async fn revision_diff(path: web::Path<(i32, i32)>, params: web::Query<DiffParams>, auth: AuthUser, pool: web::Data<Pool>) -> Result<HttpResponse, AppError> {
+    let mut conn = pool.get().await?;
+    let (post_id, revision_id) = path.into_inner();
+
+    let post = auth.authored_post(&mut conn, post_id).await?;
+    let from = models::find_revision(&mut conn, post.id, revision_id).await?;
+    let to = match params.to {
+        Some(to) => models::find_revision(&mut conn, post.id, to).await?,
+        None => models::latest_revision(&mut conn, post.id).await?.ok_or(AppError::RecordNotFound)?,
+    };
+
+    let (from_name, to_name) = (format!("revision {}", from.id), format!("revision {}", to.id));
+    let diff = RevisionDiff {
+        from: from.id,
+        to: to.id,
+        title: diff::unified(&from.title, &to.title, &from_name, &to_name),
+        body: diff::unified(&from.body, &to.body, &from_name, &to_name),
+    };
+
+    Ok(HttpResponse::Ok().json(diff))
+}
+
+async fn restore_revision(path: web::Path<(i32, i32)>, auth: AuthUser, pool: web::Data<Pool>, cache: web::Data<ListingCache>) -> Result<HttpResponse, AppError> {
+    let mut conn = pool.get().await?;
+    let (post_id, revision_id) = path.into_inner();
+
+    let post = auth.authored_post(&mut conn, post_id).await?;
+    let post = models::restore_revision(&mut conn, post.id, revision_id).await?;
+    cache.invalidate();
+
+    Ok(HttpResponse::Ok().json(post))
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/posts/{id}/revisions").route(web::get().to(post_revisions)))
+        .service(web::resource("/posts/{id}/revisions/{revision_id}/diff").route(web::get().to(revision_diff)))
+        .service(web::resource("/posts/{id}/revisions/{revision_id}/restore").route(web::post().to(restore_revision)));
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use crate::test_utils::{create_post, create_user, send, test_pool};
+    use actix_web::http::{header, StatusCode};
+    use actix_web::test::TestRequest;
+    use serde_json::json;
+
+    #[actix_web::test]
+    async fn edits_are_kept_as_revisions_to_diff_and_restore() {
+        let pool = test_pool();
+        let (alice, alice_auth) = create_user(&pool, "alice").await;
+        let (_, bob_auth) = create_user(&pool, "bob").await;
+        let post = create_post(&pool, &alice, "Hello", true).await;
+        let app = test_app!(pool);
+
+        let req = TestRequest::patch()
+            .uri(&format!("/api/posts/{}", post.id))
+            .insert_header((header::AUTHORIZATION, alice_auth.as_str()))
+            .set_json(json!({ "title": "Hello again", "body": "Body of Hello\nand more\n" }))
+            .to_request();
+        let (status, _) = send(&app, req).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let revisions = |auth: &str| {
+            TestRequest::get()
+                .uri(&format!("/api/posts/{}/revisions", post.id))
+                .insert_header((header::AUTHORIZATION, auth))
+                .to_request()
+        };
+        let (status, _) = send(&app, revisions(bob_auth.as_str())).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, page) = send(&app, revisions(alice_auth.as_str())).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(page["items"].as_array().unwrap().len(), 2);
+        assert_eq!(page["items"][0]["title"], "Hello again");
+        let first = page["items"][1]["id"].as_i64().unwrap();
+
+        let req = TestRequest::get()
+            .uri(&format!("/api/posts/{}/revisions/{}/diff", post.id, first))
+            .insert_header((header::AUTHORIZATION, alice_auth.as_str()))
+            .to_request();
+        let (status, diff) = send(&app, req).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(diff["to"], page["items"][0]["id"]);
+        assert!(diff["title"].as_str().unwrap().contains("-Hello\n"));
+        assert!(diff["title"].as_str().unwrap().contains("+Hello again\n"));
+        assert!(diff["body"].as_str().unwrap().contains("+and more\n"));
+
+        let req = TestRequest::post()
+            .uri(&format!("/api/posts/{}/revisions/{}/restore", post.id, first))
+            .insert_header((header::AUTHORIZATION, alice_auth.as_str()))
+            .to_request();
+        let (status, body) = send(&app, req).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["title"], "Hello");
+        assert_eq!(body["body"], "Body of Hello");
+
+        let (_, page) = send(&app, revisions(alice_auth.as_str())).await;
+        assert_eq!(page["items"].as_array().unwrap().len(), 3);
+        assert_eq!(page["items"][0]["title"], "Hello");
+    }
+
+    #[actix_web::test]
+    async fn revisions_of_another_post_are_not_found() {
+        let pool = test_pool();
+        let (alice, alice_auth) = create_user(&pool, "alice").await;
+        let first = create_post(&pool, &alice, "First", false).await;
+        let second = create_post(&pool, &alice, "Second", false).await;
+        let app = test_app!(pool);
+
+        let req = TestRequest::get()
+            .uri(&format!("/api/posts/{}/revisions", second.id))
+            .insert_header((header::AUTHORIZATION, alice_auth.as_str()))
+            .to_request();
+        let (_, page) = send(&app, req).await;
+        let revision = page["items"][0]["id"].as_i64().unwrap();
+
+        let req = TestRequest::post()
+            .uri(&format!("/api/posts/{}/revisions/{}/restore", first.id, revision))
+            .insert_header((header::AUTHORIZATION, alice_auth.as_str()))
+            .to_request();
+        let (status, _) = send(&app, req).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,74 @@
+use crate::errors::AppError;
+use crate::routes::{DEFAULT_PAGE_LIMIT, MAX_PAGE_LIMIT};
+use crate::{search, Pool};
+use actix_web::{web, HttpResponse};
+
+#[derive(Debug, Deserialize)]
+struct SearchParams {
+    q: String,
+    limit: Option<i64>,
+}
+
+async fn search_posts(params: web::Query<SearchParams>, pool: web::Data<Pool>) -> Result<HttpResponse, AppError> {
+    let mut conn = pool.get().await?;
+    let limit = params.limit.unwrap_or(DEFAULT_PAGE_LIMIT);
+    if !(1..=MAX_PAGE_LIMIT).contains(&limit) {
+        return Err(AppError::InvalidPage);
+    }
+
+    let results = search::search(&mut conn, params.q.as_str(), limit).await?;
+
+    Ok(HttpResponse::Ok().json(results))
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/search").route(web::get().to(search_posts)));
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use crate::test_utils::{create_post, create_user, send, test_pool};
+    use actix_web::http::StatusCode;
+    use actix_web::test::TestRequest;
+
+    #[actix_web::test]
+    async fn search_finds_published_posts_only() {
+        let pool = test_pool();
+        let (alice, _) = create_user(&pool, "alice").await;
+        let post = create_post(&pool, &alice, "Ferris the crab", true).await;
+        create_post(&pool, &alice, "Draft crab", false).await;
+        let app = test_app!(pool);
+
+        let req = TestRequest::get().uri("/api/search?q=crab").to_request();
+        let (status, hits) = send(&app, req).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(hits.as_array().unwrap().len(), 1);
+        assert_eq!(hits[0]["post"]["id"], post.id);
+        assert_eq!(hits[0]["matched"], "post");
+    }
+
+    #[actix_web::test]
+    async fn search_snippets_escape_the_body() {
+        let pool = test_pool();
+        let (alice, _) = create_user(&pool, "alice").await;
+        create_post(&pool, &alice, "<img src=x onerror=alert(1)> crab", true).await;
+        let app = test_app!(pool);
+
+        let req = TestRequest::get().uri("/api/search?q=crab").to_request();
+        let (status, hits) = send(&app, req).await;
+        assert_eq!(status, StatusCode::OK);
+        let snippet = hits[0]["snippet"].as_str().unwrap();
+        assert!(!snippet.contains("<img"), "{}", snippet);
+        assert!(snippet.contains("&lt;img src=x onerror=alert(1)&gt; <mark>crab</mark>"), "{}", snippet);
+    }
+
+    #[actix_web::test]
+    async fn blank_search_is_bad_request() {
+        let pool = test_pool();
+        let app = test_app!(pool);
+
+        let req = TestRequest::get().uri("/api/search?q=%20").to_request();
+        let (status, _) = send(&app, req).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,96 @@
+use crate::auth::{self, Secret};
+use crate::config::LimitsConfig;
+use crate::errors::AppError;
+use crate::routes::json_config;
+use crate::{models, Pool};
+use actix_web::{web, HttpResponse};
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SessionInput {
+    username: String,
+    password: String,
+}
+
+#[derive(Debug, Serialize)]
+struct SessionResponse {
+    token: String,
+    user: models::User,
+}
+
+async fn create_session(item: web::Json<SessionInput>, pool: web::Data<Pool>, secret: web::Data<Secret>) -> Result<HttpResponse, AppError> {
+    let mut conn = pool.get().await?;
+    let input = item.into_inner();
+
+    let (user, password_hash) = models::find_credentials(&mut conn, input.username.as_str()).await
+        .map_err(|err| match err {
+            AppError::RecordNotFound => AppError::Unauthorized,
+            err => err,
+        })?;
+
+    let password = input.password;
+    if !web::block(move || auth::verify_password(password.as_str(), password_hash.as_str())).await? {
+        return Err(AppError::Unauthorized);
+    }
+
+    let token = auth::issue_token(&secret, &user)?;
+    let session = SessionResponse { token, user };
+
+    Ok(HttpResponse::Ok().json(session))
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig, limits: &LimitsConfig) {
+    cfg.service(web::resource("/sessions").app_data(json_config(limits)).route(web::post().to(create_session)));
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use crate::test_utils::{send, test_pool};
+    use actix_web::http::{header, StatusCode};
+    use actix_web::test::TestRequest;
+    use serde_json::json;
+
+    #[actix_web::test]
+    async fn login_returns_token_for_write_routes() {
+        let pool = test_pool();
+        let app = test_app!(pool);
+
+        let credentials = json!({ "username": "alice", "password": "correct horse" });
+        let req = TestRequest::post().uri("/api/users").set_json(&credentials).to_request();
+        let (_, user) = send(&app, req).await;
+
+        let req = TestRequest::post().uri("/api/sessions").set_json(&credentials).to_request();
+        let (status, session) = send(&app, req).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(session["user"], user);
+
+        let token = session["token"].as_str().unwrap();
+        let req = TestRequest::post()
+            .uri(&format!("/api/users/{}/posts", user["id"]))
+            .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+            .set_json(json!({ "title": "Hello", "body": "World" }))
+            .to_request();
+        let (status, _) = send(&app, req).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn login_with_wrong_credentials_is_unauthorized() {
+        let pool = test_pool();
+        let app = test_app!(pool);
+
+        let req = TestRequest::post()
+            .uri("/api/users")
+            .set_json(json!({ "username": "alice", "password": "correct horse" }))
+            .to_request();
+        send(&app, req).await;
+
+        for credentials in &[
+            json!({ "username": "alice", "password": "wrong horse" }),
+            json!({ "username": "nobody", "password": "correct horse" }),
+        ] {
+            let req = TestRequest::post().uri("/api/sessions").set_json(credentials).to_request();
+            let (status, _) = send(&app, req).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED);
+        }
+    }
+}
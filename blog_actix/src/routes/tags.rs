@@ -0,0 +1,166 @@
+use crate::auth::{self, AuthUser};
+use crate::cache::ListingCache;
+use crate::config::LimitsConfig;
+use crate::errors::AppError;
+use crate::routes::json_config;
+use crate::{models, Pool};
+use actix_web::{web, HttpRequest, HttpResponse};
+use validator::Validate;
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+struct TagInput {
+    #[validate(length(min = 1, max = 50))]
+    name: String,
+}
+
+/// Tags are matched case-insensitively and without surrounding whitespace.
+pub(super) fn normalize_tag(name: &str) -> String {
+    name.trim().to_lowercase()
+}
+
+async fn all_tags(pool: web::Data<Pool>) -> Result<HttpResponse, AppError> {
+    let mut conn = pool.get().await?;
+    let tags = models::all_tags(&mut conn).await?;
+
+    Ok(HttpResponse::Ok().json(tags))
+}
+
+/// Like the post itself, the tags of drafts and scheduled posts are only found by their author.
+async fn post_tags(req: HttpRequest, post_id: web::Path<i32>, pool: web::Data<Pool>) -> Result<HttpResponse, AppError> {
+    let mut conn = pool.get().await?;
+    let post = models::find_visible_post(&mut conn, post_id.into_inner(), auth::token_user_id(&req)).await?;
+    let tags = models::find_post_tags(&mut conn, post.id).await?;
+
+    Ok(HttpResponse::Ok().json(tags))
+}
+
+async fn add_tag(post_id: web::Path<i32>, input: web::Json<TagInput>, auth: AuthUser, pool: web::Data<Pool>, cache: web::Data<ListingCache>) -> Result<HttpResponse, AppError> {
+    let mut conn = pool.get().await?;
+    input.validate()?;
+    let name = normalize_tag(input.name.as_str());
+    if name.is_empty() {
+        return Err(AppError::InvalidTag);
+    }
+
+    let post = auth.authored_post(&mut conn, post_id.into_inner()).await?;
+    let tags = models::tag_post(&mut conn, post.id, name.as_str()).await?;
+    cache.invalidate();
+
+    Ok(HttpResponse::Ok().json(tags))
+}
+
+async fn remove_tag(path: web::Path<(i32, String)>, auth: AuthUser, pool: web::Data<Pool>, cache: web::Data<ListingCache>) -> Result<HttpResponse, AppError> {
+    let mut conn = pool.get().await?;
+    let (post_id, name) = path.into_inner();
+
+    let post = auth.authored_post(&mut conn, post_id).await?;
+    let tags = models::untag_post(&mut conn, post.id, normalize_tag(name.as_str()).as_str()).await?;
+    cache.invalidate();
+
+    Ok(HttpResponse::Ok().json(tags))
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig, limits: &LimitsConfig) {
+    cfg.service(web::resource("/tags").route(web::get().to(all_tags)))
+        .service(
+            web::resource("/posts/{id}/tags")
+                .app_data(json_config(limits))
+                .route(web::post().to(add_tag))
+                .route(web::get().to(post_tags)),
+        )
+        .service(web::resource("/posts/{id}/tags/{name}").route(web::delete().to(remove_tag)));
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use crate::test_utils::{create_post, create_user, send, test_pool};
+    use actix_web::http::{header, StatusCode};
+    use actix_web::test::TestRequest;
+    use serde_json::json;
+
+    #[actix_web::test]
+    async fn tag_list_and_untag_post() {
+        let pool = test_pool();
+        let (alice, alice_auth) = create_user(&pool, "alice").await;
+        let post = create_post(&pool, &alice, "Hello", true).await;
+        let app = test_app!(pool);
+
+        let req = TestRequest::post()
+            .uri(&format!("/api/posts/{}/tags", post.id))
+            .insert_header((header::AUTHORIZATION, alice_auth.as_str()))
+            .set_json(json!({ "name": "  Rust " }))
+            .to_request();
+        let (status, tags) = send(&app, req).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(tags[0]["name"], "rust");
+
+        let req = TestRequest::get().uri("/api/tags").to_request();
+        let (status, tags) = send(&app, req).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(tags[0]["post_count"], 1);
+
+        let req = TestRequest::get().uri("/api/posts?tag=rust").to_request();
+        let (_, page) = send(&app, req).await;
+        assert_eq!(page["items"][0][0][0]["id"], post.id);
+
+        let req = TestRequest::delete()
+            .uri(&format!("/api/posts/{}/tags/Rust", post.id))
+            .insert_header((header::AUTHORIZATION, alice_auth.as_str()))
+            .to_request();
+        let (status, _) = send(&app, req).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let req = TestRequest::get().uri(&format!("/api/posts/{}/tags", post.id)).to_request();
+        let (status, tags) = send(&app, req).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(tags, json!([]));
+    }
+
+    #[actix_web::test]
+    async fn blank_tag_is_bad_request() {
+        let pool = test_pool();
+        let (alice, alice_auth) = create_user(&pool, "alice").await;
+        let post = create_post(&pool, &alice, "Hello", true).await;
+        let app = test_app!(pool);
+
+        let req = TestRequest::post()
+            .uri(&format!("/api/posts/{}/tags", post.id))
+            .insert_header((header::AUTHORIZATION, alice_auth.as_str()))
+            .set_json(json!({ "name": "   " }))
+            .to_request();
+        let (status, _) = send(&app, req).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn draft_tags_are_only_found_by_the_author() {
+        let pool = test_pool();
+        let (alice, alice_auth) = create_user(&pool, "alice").await;
+        let (_, bob_auth) = create_user(&pool, "bob").await;
+        let draft = create_post(&pool, &alice, "Draft", false).await;
+        let app = test_app!(pool);
+
+        let req = TestRequest::post()
+            .uri(&format!("/api/posts/{}/tags", draft.id))
+            .insert_header((header::AUTHORIZATION, alice_auth.as_str()))
+            .set_json(json!({ "name": "Rust" }))
+            .to_request();
+        let (status, _) = send(&app, req).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let tags = |auth: Option<&str>| {
+            let mut req = TestRequest::get().uri(&format!("/api/posts/{}/tags", draft.id));
+            if let Some(auth) = auth {
+                req = req.insert_header((header::AUTHORIZATION, auth));
+            }
+            req.to_request()
+        };
+        let (status, _) = send(&app, tags(None)).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) = send(&app, tags(Some(bob_auth.as_str()))).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, body) = send(&app, tags(Some(alice_auth.as_str()))).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body[0]["name"], "rust");
+    }
+}
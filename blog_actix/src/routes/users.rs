@@ -1,51 +1,189 @@
+use crate::auth;
+use crate::config::LimitsConfig;
 use crate::errors::AppError;
-use crate::routes::convert;
+use crate::rate_limit::RateLimit;
+use crate::routes::json_config;
+use crate::validation::{password, username};
 use crate::{models, Pool};
 use actix_web::{web, HttpResponse};
-use futures::Future;
+use validator::Validate;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Validate)]
 struct UserInput {
+    #[validate(custom = "username")]
     username: String,
+    #[validate(custom = "password")]
+    password: String,
 }
 
-fn create_user(item: web::Json<UserInput>, pool: web::Data<Pool>) ->
-    impl Future<Item = HttpResponse, Error = AppError> {
-        web::block(move || {
-            let connection = &pool.get().unwrap();
-            let username = item.into_inner().username;
+async fn create_user(item: web::Json<UserInput>, pool: web::Data<Pool>) -> Result<HttpResponse, AppError> {
+    let input = item.into_inner();
+    input.validate()?;
+    // bcrypt is deliberately slow, keep it off the server's threads.
+    let password = input.password;
+    let password_hash = web::block(move || auth::hash_password(password.as_str())).await??;
 
-            models::create_user(connection, username.as_str())
-        })
-        .then(convert)
+    let mut conn = pool.get().await?;
+    let user = models::create_user(&mut conn, input.username.as_str(), password_hash.as_str()).await?;
+
+    Ok(HttpResponse::Ok().json(user))
 }
 
-fn get_user(user_id: web::Path<i32>, pool: web::Data<Pool>) ->
-    impl Future<Item = HttpResponse, Error = AppError> {
-        web::block(move || {
-            let connection = &pool.get().unwrap();
-            let id = user_id.into_inner();
-            let key = models::UserKey::Id(id);
+async fn get_user(user_id: web::Path<i32>, pool: web::Data<Pool>) -> Result<HttpResponse, AppError> {
+    let mut conn = pool.get().await?;
+    let id = user_id.into_inner();
+    let key = models::UserKey::Id(id);
+
+    let user = models::find_user(&mut conn, key).await?;
 
-            models::find_user(connection, key)
-        })
-        .then(convert)
+    Ok(HttpResponse::Ok().json(user))
 }
 
-fn find_user(name: web::Path<String>, pool: web::Data<Pool>) ->
-    impl Future<Item = HttpResponse, Error = AppError> {
-        web::block(move || {
-            let connection = &pool.get().unwrap();
-            let name = name.into_inner();
-            let key = models::UserKey::Username(name.as_str());
+async fn find_user(name: web::Path<String>, pool: web::Data<Pool>) -> Result<HttpResponse, AppError> {
+    let mut conn = pool.get().await?;
+    let name = name.into_inner();
+    let key = models::UserKey::Username(name.as_str());
 
-            models::find_user(connection, key)
-        })
-        .then(convert)
+    let user = models::find_user(&mut conn, key).await?;
+
+    Ok(HttpResponse::Ok().json(user))
 }
 
-pub fn configure(cfg: &mut web::ServiceConfig) {
-    cfg.service(web::resource("/users").route(web::post().to_async(create_user)))
-        .service(web::resource("/users/find/{name}").route(web::get().to_async(find_user)))
-        .service(web::resource("/users/{id}").route(web::get().to_async(get_user)));
+pub fn configure(cfg: &mut web::ServiceConfig, limits: &LimitsConfig) {
+    cfg.service(
+            web::resource("/users")
+                .app_data(json_config(limits))
+                .route(web::post().to(create_user).wrap(RateLimit::new("create_user", limits.create_user))),
+        )
+        .service(web::resource("/users/find/{name}").route(web::get().to(find_user)))
+        .service(web::resource("/users/{id}").route(web::get().to(get_user)));
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use crate::config::LimitsConfig;
+    use crate::test_utils::{create_user, send, test_pool};
+    use actix_web::http::{header, StatusCode};
+    use actix_web::test::{self, TestRequest};
+    use serde_json::json;
+
+    #[actix_web::test]
+    async fn create_user_returns_user_without_password() {
+        let pool = test_pool();
+        let app = test_app!(pool);
+
+        let req = TestRequest::post()
+            .uri("/api/users")
+            .set_json(json!({ "username": "alice", "password": "correct horse" }))
+            .to_request();
+        let (status, body) = send(&app, req).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["username"], "alice");
+        assert!(body.get("password_hash").is_none());
+    }
+
+    #[actix_web::test]
+    async fn create_user_with_taken_username_is_bad_request() {
+        let pool = test_pool();
+        create_user(&pool, "alice").await;
+        let app = test_app!(pool);
+
+        let req = TestRequest::post()
+            .uri("/api/users")
+            .set_json(json!({ "username": "alice", "password": "correct horse" }))
+            .to_request();
+        let (status, body) = send(&app, req).await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["err"], "This record violates a unique constraint");
+    }
+
+    #[actix_web::test]
+    async fn create_user_with_invalid_fields_lists_them() {
+        let pool = test_pool();
+        let app = test_app!(pool);
+
+        let req = TestRequest::post()
+            .uri("/api/users")
+            .set_json(json!({ "username": "a b", "password": "short" }))
+            .to_request();
+        let (status, body) = send(&app, req).await;
+
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(body["fields"]["username"].is_array());
+        assert!(body["fields"]["password"].is_array());
+    }
+
+    #[actix_web::test]
+    async fn create_user_with_malformed_json_is_bad_request() {
+        let pool = test_pool();
+        let app = test_app!(pool);
+
+        let req = TestRequest::post()
+            .uri("/api/users")
+            .insert_header(("content-type", "application/json"))
+            .set_payload("{ \"username\": ")
+            .to_request();
+        let (status, _) = send(&app, req).await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn get_user_by_id_and_name() {
+        let pool = test_pool();
+        let (user, _) = create_user(&pool, "alice").await;
+        let app = test_app!(pool);
+
+        let req = TestRequest::get().uri(&format!("/api/users/{}", user.id)).to_request();
+        let (status, body) = send(&app, req).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["username"], "alice");
+
+        let req = TestRequest::get().uri("/api/users/find/alice").to_request();
+        let (status, body) = send(&app, req).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["id"], user.id);
+    }
+
+    #[actix_web::test]
+    async fn missing_user_is_not_found() {
+        let pool = test_pool();
+        let app = test_app!(pool);
+
+        let req = TestRequest::get().uri("/api/users/42").to_request();
+        let (status, body) = send(&app, req).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["err"], "This record does not exist");
+
+        let req = TestRequest::get().uri("/api/users/find/nobody").to_request();
+        let (status, _) = send(&app, req).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn create_user_is_rate_limited_per_address() {
+        let pool = test_pool();
+        let app = test_app!(pool);
+        let create = |address: &str| {
+            TestRequest::post()
+                .uri("/api/users")
+                .peer_addr(address.parse().unwrap())
+                .set_json(json!({ "username": "alice", "password": "short" }))
+                .to_request()
+        };
+
+        for _ in 0..LimitsConfig::default().create_user.burst {
+            let (status, _) = send(&app, create("192.0.2.1:4000")).await;
+            assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        }
+
+        let res = test::call_service(&app, create("192.0.2.1:4001")).await;
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(res.headers().contains_key(header::RETRY_AFTER));
+
+        let (status, _) = send(&app, create("192.0.2.2:4000")).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    }
 }
@@ -4,6 +4,28 @@ table! {
         user_id -> Integer,
         post_id -> Integer,
         body -> Text,
+        parent_id -> Nullable<Integer>,
+        body_html -> Text,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        status -> Text,
+    }
+}
+
+table! {
+    post_revisions (id) {
+        id -> Integer,
+        post_id -> Integer,
+        title -> Text,
+        body -> Text,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    post_tags (post_id, tag_id) {
+        post_id -> Integer,
+        tag_id -> Integer,
     }
 }
 
@@ -14,6 +36,20 @@ table! {
         title -> Text,
         body -> Text,
         published -> Bool,
+        body_html -> Text,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        published_at -> Nullable<Timestamp>,
+        comments_closed -> Bool,
+        comments_moderated -> Bool,
+        publish_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    tags (id) {
+        id -> Integer,
+        name -> Text,
     }
 }
 
@@ -21,15 +57,24 @@ table! {
     users (id) {
         id -> Integer,
         username -> Text,
+        password_hash -> Text,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
     }
 }
 
 joinable!(comments -> posts (post_id));
 joinable!(comments -> users (user_id));
+joinable!(post_revisions -> posts (post_id));
+joinable!(post_tags -> posts (post_id));
+joinable!(post_tags -> tags (tag_id));
 joinable!(posts -> users (user_id));
 
 allow_tables_to_appear_in_same_query!(
     comments,
+    post_revisions,
+    post_tags,
     posts,
+    tags,
     users,
 );
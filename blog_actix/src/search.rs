@@ -0,0 +1,232 @@
+use crate::db::DbConnection;
+use crate::errors::AppError;
+use crate::models::{Post, User, USER_COLUMNS};
+use crate::schema::{posts, users};
+use diesel::prelude::*;
+use diesel::sql_types::{BigInt, Double, Integer, Text};
+use diesel_async::RunQueryDsl;
+use std::collections::HashMap;
+
+type Result<T> = std::result::Result<T, AppError>;
+
+// Matches on post titles/bodies and on comment bodies. The bm25 scores of the two
+// fts tables don't compare, so each kind of hit is ranked on its own and the two
+// rankings are interleaved, a post first. The snippets mark the terms with
+// MARK_START and MARK_END, the text around them is the raw body.
+// The fts tables are kept in sync by the triggers of the create_search_index migration.
+#[cfg(feature = "sqlite")]
+const SEARCH_QUERY: &str = r#"
+    SELECT post_id, kind, snippet, rank FROM (
+        SELECT hits.post_id, hits.kind, hits.snippet, hits.rank,
+            ROW_NUMBER() OVER (PARTITION BY hits.kind ORDER BY hits.rank) AS position
+        FROM (
+            SELECT posts_fts.rowid AS post_id, 'post' AS kind,
+                snippet(posts_fts, -1, char(2), char(3), '...', 16) AS snippet,
+                bm25(posts_fts) AS rank
+            FROM posts_fts
+            WHERE posts_fts MATCH ?
+            UNION ALL
+            SELECT comments.post_id AS post_id, 'comment' AS kind,
+                snippet(comments_fts, 0, char(2), char(3), '...', 16) AS snippet,
+                bm25(comments_fts) AS rank
+            FROM comments_fts
+            INNER JOIN comments ON comments.id = comments_fts.rowid
+            WHERE comments_fts MATCH ? AND comments.status = 'approved'
+        ) AS hits
+        INNER JOIN posts ON posts.id = hits.post_id
+        WHERE posts.published = 1
+    ) AS ranked
+    ORDER BY position, kind DESC
+    LIMIT ?
+"#;
+
+// Same as above with Postgres text search, using the expression indexes of the
+// create_search_index migration. Ranks are negated so that, as with bm25, lower is better.
+#[cfg(feature = "postgres")]
+const SEARCH_QUERY: &str = r#"
+    WITH headline AS (
+        SELECT 'StartSel="' || chr(2) || '", StopSel="' || chr(3) || '", MaxWords=16, MinWords=4' AS options
+    )
+    SELECT post_id, kind, snippet, rank FROM (
+        SELECT hits.post_id, hits.kind, hits.snippet, hits.rank,
+            ROW_NUMBER() OVER (PARTITION BY hits.kind ORDER BY hits.rank) AS position
+        FROM (
+            SELECT posts.id AS post_id, 'post' AS kind,
+                ts_headline('english', posts.body, query, headline.options) AS snippet,
+                -ts_rank(to_tsvector('english', posts.title || ' ' || posts.body), query)::float8 AS rank
+            FROM posts, plainto_tsquery('english', $1) AS query, headline
+            WHERE to_tsvector('english', posts.title || ' ' || posts.body) @@ query
+            UNION ALL
+            SELECT comments.post_id AS post_id, 'comment' AS kind,
+                ts_headline('english', comments.body, query, headline.options) AS snippet,
+                -ts_rank(to_tsvector('english', comments.body), query)::float8 AS rank
+            FROM comments, plainto_tsquery('english', $1) AS query, headline
+            WHERE to_tsvector('english', comments.body) @@ query AND comments.status = 'approved'
+        ) AS hits
+        INNER JOIN posts ON posts.id = hits.post_id
+        WHERE posts.published
+    ) AS ranked
+    ORDER BY position, kind DESC
+    LIMIT $2
+"#;
+
+/// Delimiters of the matched terms in the snippets from the database.
+const MARK_START: char = '\u{2}';
+const MARK_END: char = '\u{3}';
+
+/// Escapes a snippet of a raw body and wraps its matched terms in `<mark>`.
+/// The same control characters typed in a body only ever give balanced marks.
+fn highlight(snippet: &str) -> String {
+    let mut html = String::with_capacity(snippet.len());
+    let mut marked = false;
+    for c in snippet.chars() {
+        match c {
+            MARK_START if !marked => {
+                html.push_str("<mark>");
+                marked = true;
+            }
+            MARK_END if marked => {
+                html.push_str("</mark>");
+                marked = false;
+            }
+            MARK_START | MARK_END => (),
+            '&' => html.push_str("&amp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' => html.push_str("&quot;"),
+            '\'' => html.push_str("&#39;"),
+            _ => html.push(c),
+        }
+    }
+    if marked {
+        html.push_str("</mark>");
+    }
+
+    html
+}
+
+#[derive(QueryableByName, Debug)]
+struct Hit {
+    #[diesel(sql_type = Integer)]
+    post_id: i32,
+    #[diesel(sql_type = Text)]
+    kind: String,
+    #[diesel(sql_type = Text)]
+    snippet: String,
+    #[diesel(sql_type = Double)]
+    rank: f64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SearchResult {
+    pub post: Post,
+    pub user: User,
+    pub matched: String,
+    pub snippet: String,
+    pub rank: f64,
+}
+
+/// Turns free text into an fts5 expression matching all of its terms,
+/// quoting each one so user input can't use the fts5 query syntax.
+#[cfg(feature = "sqlite")]
+fn match_expression(text: &str) -> Option<String> {
+    let terms: Vec<String> = text
+        .split_whitespace()
+        .map(|term| format!("\"{}\"", term.replace('"', "\"\"")))
+        .collect();
+
+    if terms.is_empty() {
+        None
+    } else {
+        Some(terms.join(" "))
+    }
+}
+
+#[cfg(feature = "sqlite")]
+async fn find_hits(connection: &mut DbConnection, text: &str, limit: i64) -> Result<Vec<Hit>> {
+    let expression = match_expression(text).ok_or(AppError::InvalidSearch)?;
+
+    diesel::sql_query(SEARCH_QUERY)
+        .bind::<Text, _>(&expression)
+        .bind::<Text, _>(&expression)
+        .bind::<BigInt, _>(limit)
+        .load::<Hit>(connection)
+        .await
+        .map_err(Into::into)
+}
+
+/// `plainto_tsquery` ignores any query syntax, so the text is passed as is.
+#[cfg(feature = "postgres")]
+async fn find_hits(connection: &mut DbConnection, text: &str, limit: i64) -> Result<Vec<Hit>> {
+    if text.trim().is_empty() {
+        return Err(AppError::InvalidSearch);
+    }
+
+    diesel::sql_query(SEARCH_QUERY)
+        .bind::<Text, _>(text)
+        .bind::<BigInt, _>(limit)
+        .load::<Hit>(connection)
+        .await
+        .map_err(Into::into)
+}
+
+pub async fn search(connection: &mut DbConnection, text: &str, limit: i64) -> Result<Vec<SearchResult>> {
+    let hits = find_hits(connection, text, limit).await?;
+
+    let post_ids: Vec<i32> = hits.iter().map(|hit| hit.post_id).collect();
+    let posts: HashMap<i32, (Post, User)> = posts::table
+        .filter(posts::id.eq_any(post_ids))
+        .inner_join(users::table)
+        .select((posts::all_columns, USER_COLUMNS))
+        .load::<(Post, User)>(connection)
+        .await?
+        .into_iter()
+        .map(|(post, user)| (post.id, (post, user)))
+        .collect();
+
+    Ok(hits
+        .into_iter()
+        .filter_map(|hit| {
+            posts.get(&hit.post_id).map(|(post, user)| SearchResult {
+                post: post.clone(),
+                user: user.clone(),
+                matched: hit.kind,
+                snippet: highlight(&hit.snippet),
+                rank: hit.rank,
+            })
+        })
+        .collect())
+}
+
+/// Runs against the Postgres database of `DATABASE_URL`, inside a transaction
+/// rolled back at the end, and passes without checking anything when unset.
+#[cfg(all(test, feature = "postgres"))]
+mod tests {
+    use super::*;
+    use crate::db::SyncConnection;
+    use crate::{migrations, models};
+    use diesel_async::AsyncConnection;
+
+    #[actix_web::test]
+    async fn headlines_escape_the_body() {
+        let database_url = match std::env::var("DATABASE_URL") {
+            Ok(url) => url,
+            Err(_) => return,
+        };
+        migrations::run(&mut SyncConnection::establish(&database_url).unwrap()).unwrap();
+        let mut connection = DbConnection::establish(&database_url).await.unwrap();
+        connection.begin_test_transaction().await.unwrap();
+
+        let user = models::create_user(&mut connection, "search-test", "-").await.unwrap();
+        let body = "<img src=x onerror=alert(1)> xylophonist";
+        let post = models::create_post(&mut connection, &user, "Escaping", body).await.unwrap();
+        models::publish_post(&mut connection, post.id).await.unwrap();
+
+        let results = search(&mut connection, "xylophonist", 10).await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].post.id, post.id);
+        let snippet = &results[0].snippet;
+        assert!(!snippet.replace("<mark>", "").replace("</mark>", "").contains('<'), "{}", snippet);
+        assert!(snippet.ends_with("&gt; <mark>xylophonist</mark>"), "{}", snippet);
+    }
+}
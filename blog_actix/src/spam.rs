@@ -0,0 +1,25 @@
+//! Heuristics flagging likely spam comments, which then wait for approval
+//! instead of being listed right away.
+
+use chrono::Duration;
+
+/// Links allowed in a comment before it is flagged.
+const MAX_LINKS: usize = 2;
+/// Shorter bodies, such as "Thanks!", are often legitimately repeated.
+const MIN_DUPLICATE_LENGTH: usize = 20;
+
+/// How far back a comment counts as a duplicate of a new one with the same body.
+pub fn duplicate_window() -> Duration {
+    Duration::hours(24)
+}
+
+fn link_count(body: &str) -> usize {
+    let body = body.to_lowercase();
+    body.matches("http://").count() + body.matches("https://").count()
+}
+
+/// Whether a new comment should be flagged. `duplicate` tells whether its author
+/// already posted the same body within `duplicate_window`.
+pub fn is_suspicious(body: &str, duplicate: bool) -> bool {
+    link_count(body) > MAX_LINKS || (duplicate && body.trim().chars().count() >= MIN_DUPLICATE_LENGTH)
+}
@@ -0,0 +1,91 @@
+//! Helpers for the route tests, which run against a fresh in-memory database.
+
+use crate::auth::{self, Secret};
+use crate::db::{DbConnection, SyncConnection};
+use crate::{migrations, models, Pool};
+use actix_web::body::MessageBody;
+use actix_web::dev::{Service, ServiceResponse};
+use actix_web::http::StatusCode;
+use actix_web::test;
+use diesel::{Connection, ConnectionResult};
+use diesel_async::pooled_connection::{AsyncDieselConnectionManager, ManagerConfig};
+use futures::future::{BoxFuture, FutureExt};
+use std::fmt::Debug;
+
+/// Builds the app on `$pool` with the routes and data of `Blog::run`, without binding a port.
+macro_rules! test_app {
+    ($pool:expr) => {
+        actix_web::test::init_service(actix_web::App::new().configure(|cfg| {
+            crate::configure_app(
+                cfg,
+                $pool.clone(),
+                crate::test_utils::secret(),
+                crate::cache::ListingCache::default(),
+                crate::rate_limit::RateLimiter::default(),
+                crate::feeds::SiteUrl(String::from("http://blog.test")),
+                &crate::config::LimitsConfig::default(),
+            )
+        }))
+        .await
+    };
+}
+
+fn establish_in_memory(_: &str) -> BoxFuture<'_, ConnectionResult<DbConnection>> {
+    async {
+        let mut connection = SyncConnection::establish(":memory:")?;
+        migrations::run(&mut connection).expect("Failed to migrate the test database");
+
+        Ok(DbConnection::new(connection))
+    }
+    .boxed()
+}
+
+/// An in-memory database with every migration applied. It only lives as long
+/// as its single connection, which the pool keeps and never replaces.
+pub fn test_pool() -> Pool {
+    let mut config = ManagerConfig::default();
+    config.custom_setup = Box::new(establish_in_memory);
+
+    Pool::builder(AsyncDieselConnectionManager::new_with_config(":memory:", config))
+        .max_size(1)
+        .build()
+        .expect("Failed to create the test database")
+}
+
+pub fn secret() -> Secret {
+    Secret(String::from("test-secret"))
+}
+
+/// Creates a user directly in the database and returns it with the value of
+/// its `Authorization` header. Its password hash is not valid bcrypt, so it
+/// can't log in through `/api/sessions`.
+pub async fn create_user(pool: &Pool, username: &str) -> (models::User, String) {
+    let user = models::create_user(&mut pool.get().await.unwrap(), username, "-").await.unwrap();
+    let token = auth::issue_token(&secret(), &user).unwrap();
+
+    (user, format!("Bearer {}", token))
+}
+
+pub async fn create_post(pool: &Pool, user: &models::User, title: &str, published: bool) -> models::Post {
+    let connection: &mut DbConnection = &mut pool.get().await.unwrap();
+    let post = models::create_post(connection, user, title, &format!("Body of {}", title)).await.unwrap();
+    if published {
+        models::publish_post(connection, post.id).await.unwrap()
+    } else {
+        post
+    }
+}
+
+/// Sends `req` and returns the status with the body parsed as JSON, `Null` if it isn't.
+pub async fn send<S, R, B, E>(app: &S, req: R) -> (StatusCode, serde_json::Value)
+where
+    S: Service<R, Response = ServiceResponse<B>, Error = E>,
+    B: MessageBody,
+    E: Debug,
+{
+    let res = test::call_service(app, req).await;
+    let status = res.status();
+    let body = test::read_body(res).await;
+
+    (status, serde_json::from_slice(&body).unwrap_or(serde_json::Value::Null))
+}
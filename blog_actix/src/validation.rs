@@ -0,0 +1,55 @@
+use std::borrow::Cow;
+use validator::ValidationError;
+
+fn error(code: &'static str, message: &'static str) -> ValidationError {
+    let mut error = ValidationError::new(code);
+    error.message = Some(Cow::from(message));
+    error
+}
+
+pub fn not_blank(value: &str) -> Result<(), ValidationError> {
+    if value.trim().is_empty() {
+        return Err(error("blank", "must not be blank"));
+    }
+
+    Ok(())
+}
+
+/// Non blank text without control characters, such as line breaks.
+pub fn single_line(value: &str) -> Result<(), ValidationError> {
+    not_blank(value)?;
+    if value.chars().any(char::is_control) {
+        return Err(error("control_chars", "must not contain control characters"));
+    }
+
+    Ok(())
+}
+
+fn username_chars(value: &str) -> Result<(), ValidationError> {
+    let valid = value
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.');
+    if !valid {
+        return Err(error("username_chars", "may only contain letters, digits, '_', '-' and '.'"));
+    }
+
+    Ok(())
+}
+
+/// The passwords of the API, also checked by `blog-admin`.
+pub fn password(value: &str) -> Result<(), ValidationError> {
+    if !(8..=128).contains(&value.chars().count()) {
+        return Err(error("length", "must be 8 to 128 characters long"));
+    }
+
+    Ok(())
+}
+
+/// The usernames of the API, also checked by `blog-admin`.
+pub fn username(value: &str) -> Result<(), ValidationError> {
+    if !(3..=32).contains(&value.chars().count()) {
+        return Err(error("length", "must be 3 to 32 characters long"));
+    }
+
+    username_chars(value)
+}